@@ -0,0 +1,77 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use bevy::render::mesh::MeshPlugin;
+use bevy::state::app::StatesPlugin;
+
+use crate::config::{RenderMode, SimulationConfig};
+use crate::instancing::{CubeSwarm, InstanceMaterialData, InstancingPlugin};
+use crate::simulation::{InitPlugin, StepGeneration};
+
+// Compares the entity-per-cube scene with the instanced one on `size`
+// individuals: `frames` frames running one generation each, then `frames`
+// frames running none. There is no window or GPU, so this times the
+// simulation side of a frame: the GA, spawning or syncing the cubes, moving
+// them and filling the instance data.
+pub fn run(config: &SimulationConfig, size: usize, frames: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = config.clone();
+    config.population_size = size;
+    // Elites must leave room for at least one child
+    config.elite_count = config.elite_count.min(size.saturating_sub(1));
+    config.seed = Some(config.seed.unwrap_or(0));
+    config.validate()?;
+    println!("{size} individuals, {frames} frames, seed {}", config.seed.unwrap_or_default());
+
+    for render_mode in [RenderMode::Entities, RenderMode::Instanced] {
+        config.render_mode = render_mode;
+        let mut app = bench_app(&config);
+        // Startup and the first generation's cubes are not timed
+        app.update();
+
+        let started = Instant::now();
+        for _ in 0..frames {
+            app.world_mut().send_event(StepGeneration);
+            app.update();
+        }
+        let generation_frame = started.elapsed().as_secs_f64() / frames as f64;
+        // Frames between two generations only move and draw the cubes
+        let started = Instant::now();
+        for _ in 0..frames {
+            app.update();
+        }
+        let frame = started.elapsed().as_secs_f64() / frames as f64;
+
+        let world = app.world_mut();
+        let entities = world.entities().len();
+        let instances: usize = world.query::<&InstanceMaterialData>().iter(world).map(|data| data.len()).sum();
+        // One rebuild per generation plus the first, more means idle frames
+        // rebuild the swarm too
+        let rebuilds = world.resource::<CubeSwarm>().rebuilds;
+        println!(
+            "{:<9}  generation frame {:>9.2} ms  frame {:>8.2} ms  entities {:>7}  instances {:>7}  swarm rebuilds {:>5}",
+            format!("{render_mode:?}"),
+            generation_frame * 1000.0,
+            frame * 1000.0,
+            entities,
+            instances,
+            rebuilds
+        );
+    }
+    Ok(())
+}
+
+fn bench_app(config: &SimulationConfig) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        MeshPlugin,
+        TransformPlugin,
+        HierarchyPlugin,
+        StatesPlugin,
+    ))
+    .init_asset::<StandardMaterial>()
+    .insert_resource(config.clone())
+    .add_plugins((InitPlugin, InstancingPlugin));
+    app
+}
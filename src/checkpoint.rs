@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use algogen::ga::{
+    Archipelago, ArchipelagoState, CubeGenome, GaRng, HallOfFame, Population, PopulationState, StatsHistory,
+    TerminationState,
+};
+
+use crate::config::{ConfigError, SimulationConfig};
+
+// Bumped whenever the layout of a checkpoint changes, older files are refused
+pub const CHECKPOINT_VERSION: u32 = 7;
+
+// A whole run saved to JSON: settings, RNG state, population and history.
+// Loading one resumes the run exactly where it was saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub config: SimulationConfig,
+    pub seed: u64,
+    pub rng: GaRng,
+    pub population: PopulationState<CubeGenome>,
+    // Every island of an island run
+    pub islands: Option<ArchipelagoState<CubeGenome>>,
+    pub history: StatsHistory,
+    pub hall_of_fame: HallOfFame,
+    // Progress towards the stagnation criterion
+    pub termination: TerminationState,
+    // Where the parent cubes were and how they moved, empty for headless runs
+    pub cubes: Vec<CubeState>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CubeState {
+    pub id: u64,
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_json::Error),
+    Version(PathBuf, u32),
+    Config(PathBuf, Box<ConfigError>),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(path, err) => write!(f, "cannot access {}: {}", path.display(), err),
+            CheckpointError::Parse(path, err) => write!(f, "cannot parse checkpoint {}: {}", path.display(), err),
+            CheckpointError::Version(path, version) => write!(
+                f,
+                "checkpoint {} has version {}, only version {} is supported",
+                path.display(),
+                version,
+                CHECKPOINT_VERSION
+            ),
+            CheckpointError::Config(path, err) => write!(f, "checkpoint {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl Checkpoint {
+    // The population settings edited since startup are saved along with the config
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: &SimulationConfig,
+        population: &Population,
+        islands: Option<&Archipelago>,
+        seed: u64,
+        rng: &GaRng,
+        history: &StatsHistory,
+        hall_of_fame: &HallOfFame,
+        termination: TerminationState,
+        cubes: Vec<CubeState>,
+    ) -> Self {
+        let mut config = config.clone();
+        config.capture(population);
+        if let Some(islands) = islands {
+            config.islands.migration = islands.migration;
+        }
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            config,
+            seed,
+            rng: rng.clone(),
+            population: population.state(),
+            islands: islands.map(|islands| islands.state()),
+            history: history.clone(),
+            hall_of_fame: hall_of_fame.clone(),
+            termination,
+            cubes,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CheckpointError> {
+        let io_error = |err| CheckpointError::Io(path.to_path_buf(), err);
+        let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+        serde_json::to_writer(&mut writer, self).map_err(|err| CheckpointError::Parse(path.to_path_buf(), err))?;
+        writer.flush().map_err(io_error)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CheckpointError> {
+        let text = std::fs::read_to_string(path).map_err(|err| CheckpointError::Io(path.to_path_buf(), err))?;
+        let parse_error = |err| CheckpointError::Parse(path.to_path_buf(), err);
+
+        // Check the version first so old files get a clear error
+        let header: Header = serde_json::from_str(&text).map_err(parse_error)?;
+        if header.version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::Version(path.to_path_buf(), header.version));
+        }
+        let checkpoint: Checkpoint = serde_json::from_str(&text).map_err(parse_error)?;
+        checkpoint
+            .config
+            .validate()
+            .map_err(|err| CheckpointError::Config(path.to_path_buf(), Box::new(err)))?;
+        Ok(checkpoint)
+    }
+
+    pub fn population(&self) -> Population {
+        let mut population = Population::new(self.config.cube_params(), self.config.fitness);
+        self.config.configure(&mut population);
+        population.restore(self.population.clone());
+        population
+    }
+
+    // Built on top of the restored `population`
+    pub fn archipelago(&self, population: &Population) -> Option<Archipelago> {
+        let state = self.islands.clone()?;
+        let islands = &self.config.islands;
+        Some(Archipelago::restore(population, islands.settings.clone(), islands.migration, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algogen::ga::{seeded_rng, TerminationState};
+
+    // A run going on in memory, island runs included
+    struct Run {
+        rng: GaRng,
+        population: Population,
+        islands: Option<Archipelago>,
+    }
+
+    impl Run {
+        fn step(&mut self, config: &SimulationConfig, generations: u32) {
+            for _ in 0..generations {
+                match &mut self.islands {
+                    Some(islands) => {
+                        islands.evolve(&mut self.population, &mut self.rng);
+                        islands.cull(&mut self.population, config.cull_fitness);
+                    }
+                    None => {
+                        self.population.step(&mut self.rng);
+                        self.population.cull(config.cull_fitness);
+                    }
+                }
+            }
+        }
+
+        fn state(&self) -> String {
+            let islands = self.islands.as_ref().map(|islands| islands.state());
+            // The Debug of the RNG shows its buffer, not where it is
+            format!("{:?} {:?} {}", self.population.state(), islands, self.rng.get_word_pos())
+        }
+    }
+
+    // Saving, loading and going on gives the same generations as never
+    // stopping
+    fn resumes_exactly(config: SimulationConfig, name: &str) {
+        let seed = config.seed.unwrap();
+        let mut rng = seeded_rng(seed);
+        let mut population = config.population(&mut rng);
+        let islands = config.archipelago(&mut population, &mut rng);
+        let mut run = Run { rng, population, islands };
+        run.step(&config, 30);
+
+        let path = std::env::temp_dir().join(format!("algogen_{name}_{}.json", std::process::id()));
+        Checkpoint::new(
+            &config,
+            &run.population,
+            run.islands.as_ref(),
+            seed,
+            &run.rng,
+            &StatsHistory::new(seed),
+            &HallOfFame::new(config.hall_of_fame_size),
+            TerminationState::default(),
+            Vec::new(),
+        )
+        .save(&path)
+        .unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let population = checkpoint.population();
+        let islands = checkpoint.archipelago(&population);
+        let mut resumed = Run {
+            rng: checkpoint.rng.clone(),
+            population,
+            islands,
+        };
+        assert_eq!(resumed.state(), run.state());
+
+        run.step(&config, 30);
+        resumed.step(&checkpoint.config, 30);
+        assert_eq!(resumed.state(), run.state());
+    }
+
+    #[test]
+    fn resumed_run_matches_uninterrupted_one() {
+        let config = SimulationConfig {
+            seed: Some(11),
+            population_size: 80,
+            ..Default::default()
+        };
+        resumes_exactly(config, "single");
+    }
+
+    #[test]
+    fn resumed_island_run_matches_uninterrupted_one() {
+        let mut config = SimulationConfig {
+            seed: Some(12),
+            population_size: 40,
+            ..Default::default()
+        };
+        config.islands.count = 3;
+        config.islands.migration.interval = 5;
+        resumes_exactly(config, "islands");
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        let path = std::env::temp_dir().join(format!("algogen_version_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"version": 1}"#).unwrap();
+        let loaded = Checkpoint::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(CheckpointError::Version(_, 1))));
+    }
+}
@@ -0,0 +1,596 @@
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use algogen::ga::{
+    Archipelago, ColorFitness, CrossoverOperator, CubeParams, IslandSettings, Migration, Mutation, MutationOperator,
+    Neighborhood, Niching, Population, SelectionStrategy, ReplacementModel, TerminationCriteria,
+};
+use rand::Rng;
+
+// Every tunable of the simulation, loaded from a TOML file given with
+// `--config <path>`. Missing keys keep their default value.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    // Seed of the simulation RNG, a random one is picked when left out
+    pub seed: Option<u64>,
+    // mu, every generation is brought back to this many individuals
+    pub population_size: usize,
+    // How the offspring replace the population
+    pub replacement: ReplacementModel,
+    // Individuals scoring below this are eliminated every generation
+    pub cull_fitness: f32,
+    // Cubes bounce inside [-world_bounds, world_bounds] on every axis
+    pub world_bounds: f32,
+    pub speed_multiplier: f32,
+    // How the cubes are drawn, see `RenderMode`
+    pub render_mode: RenderMode,
+    // How many generations run every second, changeable from the control window
+    pub generations_per_second: f64,
+    // Initial velocities are drawn in [-max_velocity, max_velocity)
+    pub max_velocity: f32,
+    pub spawn_min: [f32; 3],
+    pub spawn_max: [f32; 3],
+    pub selection: SelectionStrategy,
+    pub crossover: CrossoverOperator,
+    pub crossover_rate: f32,
+    pub two_offspring: bool,
+    pub mutations: Vec<Mutation>,
+    // Best individuals copied unchanged into every new generation
+    pub elite_count: usize,
+    // Who mates with whom. Radius and Lattice only pair up cubes living
+    // close to each other, see `Neighborhood`
+    pub neighborhood: Neighborhood,
+    // How diversity is protected, see `Niching`
+    pub niching: Niching,
+    pub fitness: ColorFitness,
+    // Fitness scores kept so unchanged genomes aren't re-scored, 0 turns the
+    // cache off
+    pub fitness_cache_size: usize,
+    // Threads breeding and scoring the population, every core when left out
+    pub threads: Option<usize>,
+    // How many of the best individuals ever seen are kept
+    pub hall_of_fame_size: usize,
+    // When the run stops, it goes on until stopped by hand by default
+    pub termination: TerminationCriteria,
+    // Sub-populations, a single one by default
+    pub islands: IslandConfig,
+    // Cubes breeding when they bump into each other, off by default
+    pub encounters: EncounterConfig,
+}
+
+// With more than one island, every island evolves its own population of
+// population_size individuals and a few of them migrate now and then
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IslandConfig {
+    pub count: usize,
+    pub migration: Migration,
+    // Island i overrides the shared GA settings with settings[i]
+    pub settings: Vec<IslandSettings>,
+}
+
+impl Default for IslandConfig {
+    fn default() -> Self {
+        IslandConfig {
+            count: 1,
+            migration: Migration::default(),
+            settings: Vec::new(),
+        }
+    }
+}
+
+// Encounter mode: instead of breeding a whole generation at once, two mature
+// cubes whose boxes overlap may mate right there. Generations still tick,
+// but only to take stats and cull, children are scored when they're born.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncounterConfig {
+    pub enabled: bool,
+    // Seconds a cube lives before it can mate
+    pub maturity: f32,
+    // Seconds between two matings of the same cube
+    pub cooldown: f32,
+    // A cube accepts a mate with probability mate_fitness^choosiness, 0
+    // accepts anyone
+    pub choosiness: f32,
+}
+
+impl Default for EncounterConfig {
+    fn default() -> Self {
+        EncounterConfig {
+            enabled: false,
+            maturity: 2.0,
+            cooldown: 5.0,
+            choosiness: 1.0,
+        }
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        let params = CubeParams::default();
+        SimulationConfig {
+            seed: None,
+            population_size: 350,
+            replacement: ReplacementModel::default(),
+            cull_fitness: 0.2,
+            world_bounds: params.bounds,
+            speed_multiplier: 3.0,
+            render_mode: RenderMode::default(),
+            generations_per_second: 10.0,
+            max_velocity: 0.1,
+            spawn_min: params.spawn_min,
+            spawn_max: params.spawn_max,
+            selection: SelectionStrategy::default(),
+            crossover: CrossoverOperator::default(),
+            crossover_rate: 1.0,
+            two_offspring: false,
+            // Unbiased defaults, the yellow bias can be added from the control window
+            mutations: vec![
+                Mutation::new(MutationOperator::Reset, 0.001),
+                Mutation::new(MutationOperator::Creep { step: 0.001 }, 1.0),
+            ],
+            elite_count: 2,
+            neighborhood: Neighborhood::default(),
+            niching: Niching::default(),
+            fitness: ColorFitness::default(),
+            fitness_cache_size: 100_000,
+            threads: None,
+            hall_of_fame_size: 10,
+            termination: TerminationCriteria::default(),
+            islands: IslandConfig::default(),
+            encounters: EncounterConfig::default(),
+        }
+    }
+}
+
+// Entities gives every cube and gene its own entity, Instanced draws the
+// whole population from one instance buffer and moves it in flat arrays,
+// which keeps very large populations interactive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RenderMode {
+    #[default]
+    Entities,
+    Instanced,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "cannot parse {}: {}", path.display(), err),
+            ConfigError::Invalid(message) => write!(f, "invalid configuration: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl SimulationConfig {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+        let config: SimulationConfig =
+            toml::from_str(&text).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+        let is_rate = |value: f32| (0.0..=1.0).contains(&value);
+
+        if self.population_size < 2 {
+            return invalid(format!("population_size must be at least 2, got {}", self.population_size));
+        }
+        if !is_rate(self.cull_fitness) {
+            return invalid(format!("cull_fitness must be in [0, 1], got {}", self.cull_fitness));
+        }
+        check_replacement("", self.replacement, self.population_size, self.elite_count)?;
+        if !is_positive(self.world_bounds) {
+            return invalid(format!("world_bounds must be positive, got {}", self.world_bounds));
+        }
+        if !(self.generations_per_second > 0.0 && self.generations_per_second.is_finite()) {
+            return invalid(format!(
+                "generations_per_second must be positive, got {}",
+                self.generations_per_second
+            ));
+        }
+        if !is_positive(self.max_velocity) {
+            return invalid(format!("max_velocity must be positive, got {}", self.max_velocity));
+        }
+        for axis in 0..3 {
+            let (min, max) = (self.spawn_min[axis], self.spawn_max[axis]);
+            if min >= max {
+                return invalid(format!("spawn_min[{axis}] ({min}) must be below spawn_max[{axis}] ({max})"));
+            }
+            if min < -self.world_bounds || max > self.world_bounds {
+                return invalid(format!(
+                    "spawn range [{min}, {max}] on axis {axis} leaves the world bounds of {}",
+                    self.world_bounds
+                ));
+            }
+        }
+
+        check_selection("", self.selection)?;
+        check_crossover("", self.crossover)?;
+        if !is_rate(self.crossover_rate) {
+            return invalid(format!("crossover_rate must be in [0, 1], got {}", self.crossover_rate));
+        }
+        check_mutations("", &self.mutations)?;
+        // Elites filling the whole population would leave no room for children
+        if self.elite_count >= self.population_size {
+            return invalid(format!(
+                "elite_count ({}) must be below population_size ({})",
+                self.elite_count, self.population_size
+            ));
+        }
+        match self.neighborhood {
+            Neighborhood::Radius { radius } if !is_positive(radius) => {
+                return invalid(format!("neighborhood.radius must be positive, got {radius}"));
+            }
+            Neighborhood::Lattice { spacing, .. } if !is_positive(spacing) => {
+                return invalid(format!("neighborhood.spacing must be positive, got {spacing}"));
+            }
+            Neighborhood::Lattice { range: 0, .. } => {
+                return invalid("neighborhood.range must be at least 1".to_string());
+            }
+            _ => {}
+        }
+        match self.niching {
+            Niching::Sharing { radius, alpha } if !is_positive(radius) || !is_positive(alpha) => {
+                return invalid(format!("niching.radius and alpha must be positive, got {radius} and {alpha}"));
+            }
+            Niching::Clearing { radius, .. } if !is_positive(radius) => {
+                return invalid(format!("niching.radius must be positive, got {radius}"));
+            }
+            Niching::Clearing { capacity: 0, .. } => {
+                return invalid("niching.capacity must be at least 1".to_string());
+            }
+            Niching::Speciation { threshold } if !is_positive(threshold) => {
+                return invalid(format!("niching.threshold must be positive, got {threshold}"));
+            }
+            // Both decide who replaces whom, they can't be combined
+            Niching::Crowding if self.neighborhood.is_spatial() => {
+                return invalid("niching Crowding needs a Panmictic neighborhood".to_string());
+            }
+            _ => {}
+        }
+        if self.threads == Some(0) {
+            return invalid("threads must be at least 1".to_string());
+        }
+        if self.hall_of_fame_size < 1 {
+            return invalid("hall_of_fame_size must be at least 1".to_string());
+        }
+        let termination = &self.termination;
+        if termination.max_generations == Some(0) {
+            return invalid("termination.max_generations must be at least 1".to_string());
+        }
+        if termination.stagnation == Some(0) {
+            return invalid("termination.stagnation must be at least 1".to_string());
+        }
+        if let Some(seconds) = termination.max_seconds.filter(|&seconds| seconds.is_nan() || seconds <= 0.0) {
+            return invalid(format!("termination.max_seconds must be positive, got {seconds}"));
+        }
+        if let Some(share) = termination.allele_fixation.filter(|&share| !(share > 0.0 && share <= 1.0)) {
+            return invalid(format!("termination.allele_fixation must be in (0, 1], got {share}"));
+        }
+        let islands = &self.islands;
+        if islands.count < 1 {
+            return invalid("islands.count must be at least 1".to_string());
+        }
+        if islands.migration.interval < 1 {
+            return invalid("islands.migration.interval must be at least 1".to_string());
+        }
+        if islands.settings.len() > islands.count {
+            return invalid(format!(
+                "islands.settings has {} entries for {} islands",
+                islands.settings.len(),
+                islands.count
+            ));
+        }
+        for (i, settings) in islands.settings.iter().enumerate() {
+            let prefix = format!("islands.settings[{i}].");
+            let size = settings.population_size.unwrap_or(self.population_size);
+            if size < 2 || size <= self.elite_count {
+                return invalid(format!("{prefix}population_size must be at least 2 and above elite_count, got {size}"));
+            }
+            // The replacement model is shared, it has to fit every island
+            check_replacement(&prefix, self.replacement, size, self.elite_count)?;
+            if let Some(selection) = settings.selection {
+                check_selection(&prefix, selection)?;
+            }
+            if let Some(crossover) = settings.crossover {
+                check_crossover(&prefix, crossover)?;
+            }
+            if let Some(rate) = settings.crossover_rate.filter(|&rate| !is_rate(rate)) {
+                return invalid(format!("{prefix}crossover_rate must be in [0, 1], got {rate}"));
+            }
+            if let Some(mutations) = &settings.mutations {
+                check_mutations(&prefix, mutations)?;
+            }
+        }
+        let encounters = &self.encounters;
+        if !is_parameter(encounters.maturity) || !is_parameter(encounters.cooldown) || !is_parameter(encounters.choosiness) {
+            return invalid(format!(
+                "encounters.maturity, cooldown and choosiness cannot be negative, got {}, {} and {}",
+                encounters.maturity, encounters.cooldown, encounters.choosiness
+            ));
+        }
+        // Cubes need to roam the whole world to meet
+        if encounters.enabled && (islands.count > 1 || self.neighborhood.is_spatial()) {
+            return invalid("encounters only work with one island and a Panmictic neighborhood".to_string());
+        }
+        if !self.fitness.target.iter().all(|&channel| is_rate(channel)) {
+            return invalid(format!("fitness.target channels must be in [0, 1], got {:?}", self.fitness.target));
+        }
+        Ok(())
+    }
+
+    pub fn cube_params(&self) -> CubeParams {
+        CubeParams {
+            spawn_min: self.spawn_min,
+            spawn_max: self.spawn_max,
+            bounds: self.world_bounds,
+        }
+    }
+
+    // A fresh random population using every GA setting of the config
+    pub fn population<R: Rng + ?Sized>(&self, rng: &mut R) -> Population {
+        let mut population = Population::random(self.population_size, self.cube_params(), self.fitness, rng);
+        self.configure(&mut population);
+        population
+    }
+
+    // The islands of an island run, None with a single island. `population`
+    // gets the union of the islands.
+    pub fn archipelago<R: Rng + ?Sized>(&self, population: &mut Population, rng: &mut R) -> Option<Archipelago> {
+        if self.islands.count < 2 {
+            return None;
+        }
+        let islands = Archipelago::new(
+            population,
+            self.islands.count,
+            self.islands.settings.clone(),
+            self.islands.migration,
+            rng,
+        );
+        islands.merge_into(population);
+        Some(islands)
+    }
+
+    pub fn configure(&self, population: &mut Population) {
+        population.size = self.population_size;
+        population.replacement = self.replacement;
+        population.selection = self.selection;
+        population.crossover = self.crossover;
+        population.crossover_rate = self.crossover_rate;
+        population.two_offspring = self.two_offspring;
+        population.mutations = self.mutations.clone();
+        population.elite_count = self.elite_count;
+        population.neighborhood = self.neighborhood;
+        population.niching = self.niching;
+        population.cache.capacity = self.fitness_cache_size;
+    }
+
+    // Sizes rayon's global pool, only the first call has any effect
+    pub fn init_threads(&self) -> Result<(), rayon::ThreadPoolBuildError> {
+        match self.threads {
+            Some(threads) => rayon::ThreadPoolBuilder::new().num_threads(threads).build_global(),
+            None => Ok(()),
+        }
+    }
+
+    // The control window edits the population directly, this copies its
+    // settings back so they can be saved
+    pub fn capture(&mut self, population: &Population) {
+        self.population_size = population.size;
+        self.replacement = population.replacement;
+        self.selection = population.selection;
+        self.crossover = population.crossover;
+        self.crossover_rate = population.crossover_rate;
+        self.two_offspring = population.two_offspring;
+        self.mutations = population.mutations.clone();
+        self.elite_count = population.elite_count;
+        self.neighborhood = population.neighborhood;
+        self.niching = population.niching;
+        self.fitness_cache_size = population.cache.capacity;
+    }
+}
+
+// The checks below are shared by the base settings and every island
+// override, `prefix` tells them apart in the messages
+
+fn check_replacement(
+    prefix: &str,
+    replacement: ReplacementModel,
+    population_size: usize,
+    elite_count: usize,
+) -> Result<(), ConfigError> {
+    let invalid = |message: String| Err(ConfigError::Invalid(message));
+    match replacement {
+        ReplacementModel::SteadyState { replaced } if replaced < 1 || replaced > population_size => invalid(format!(
+            "replacement.replaced must be in [1, {prefix}population_size ({population_size})], got {replaced}"
+        )),
+        ReplacementModel::MuPlusLambda { lambda } if lambda < 1 => {
+            invalid("replacement.lambda must be at least 1".to_string())
+        }
+        ReplacementModel::MuCommaLambda { lambda } if lambda + elite_count < population_size => invalid(format!(
+            "replacement.lambda ({lambda}) plus elite_count must reach {prefix}population_size ({population_size}) for (mu,lambda)"
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_selection(prefix: &str, selection: SelectionStrategy) -> Result<(), ConfigError> {
+    let invalid = |message: String| Err(ConfigError::Invalid(message));
+    match selection {
+        SelectionStrategy::Tournament { k } if k < 1 => invalid(format!("{prefix}selection.k must be at least 1")),
+        SelectionStrategy::Truncation { fraction } if !(fraction > 0.0 && fraction <= 1.0) => {
+            invalid(format!("{prefix}selection.fraction must be in (0, 1], got {fraction}"))
+        }
+        SelectionStrategy::Boltzmann { temperature } if !(temperature > 0.0 && temperature.is_finite()) => {
+            invalid(format!("{prefix}selection.temperature must be positive, got {temperature}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Negative or NaN parameters make the operators panic or breed NaN genes
+fn check_crossover(prefix: &str, crossover: CrossoverOperator) -> Result<(), ConfigError> {
+    let invalid = |message: String| Err(ConfigError::Invalid(message));
+    match crossover {
+        CrossoverOperator::BlendAlpha { alpha } if !is_parameter(alpha) => {
+            invalid(format!("{prefix}crossover.alpha must be zero or positive, got {alpha}"))
+        }
+        CrossoverOperator::SimulatedBinary { eta } if !is_parameter(eta) => {
+            invalid(format!("{prefix}crossover.eta must be zero or positive, got {eta}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_mutations(prefix: &str, mutations: &[Mutation]) -> Result<(), ConfigError> {
+    let invalid = |message: String| Err(ConfigError::Invalid(message));
+    for (i, mutation) in mutations.iter().enumerate() {
+        if !(0.0..=1.0).contains(&mutation.rate) {
+            return invalid(format!("{prefix}mutations[{i}].rate must be in [0, 1], got {}", mutation.rate));
+        }
+        let (name, value) = match mutation.operator {
+            MutationOperator::Creep { step } => ("step", step),
+            MutationOperator::Gaussian { sigma } => ("sigma", sigma),
+            MutationOperator::Polynomial { eta } => ("eta", eta),
+            MutationOperator::YellowBias { growth } => ("growth", growth),
+            _ => continue,
+        };
+        if !is_parameter(value) {
+            return invalid(format!("{prefix}mutations[{i}].{name} must be zero or positive, got {value}"));
+        }
+    }
+    Ok(())
+}
+
+// Finite and not negative, NaN fails too
+fn is_parameter(value: f32) -> bool {
+    value >= 0.0 && value.is_finite()
+}
+
+// Finite and above 0, NaN fails too
+fn is_positive(value: f32) -> bool {
+    value > 0.0 && value.is_finite()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algogen::ga::IslandSettings;
+
+    fn rejects(config: &SimulationConfig, key: &str) {
+        match config.validate() {
+            Err(ConfigError::Invalid(message)) => assert!(message.contains(key), "{key} not in: {message}"),
+            other => panic!("expected {key} to be refused, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        SimulationConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn refuses_bad_population_settings() {
+        let mut config = SimulationConfig {
+            population_size: 1,
+            ..Default::default()
+        };
+        rejects(&config, "population_size");
+        config.population_size = 10;
+        config.elite_count = 10;
+        rejects(&config, "elite_count");
+        config.elite_count = 2;
+        config.replacement = ReplacementModel::MuCommaLambda { lambda: 5 };
+        rejects(&config, "replacement.lambda");
+        config.replacement = ReplacementModel::default();
+        config.spawn_max = [config.world_bounds + 1.0; 3];
+        rejects(&config, "world bounds");
+    }
+
+    #[test]
+    fn parses_toml_and_refuses_unknown_keys() {
+        let config: SimulationConfig = toml::from_str("population_size = 50\n[termination]\nstagnation = 20").unwrap();
+        assert_eq!(config.population_size, 50);
+        assert_eq!(config.termination.stagnation, Some(20));
+        assert!(toml::from_str::<SimulationConfig>("populaton_size = 50").is_err());
+    }
+
+    #[test]
+    fn refuses_bad_operator_parameters() {
+        let mut config = SimulationConfig {
+            mutations: vec![Mutation::new(MutationOperator::Creep { step: -0.1 }, 1.0)],
+            ..Default::default()
+        };
+        rejects(&config, "mutations[0].step");
+        config.mutations = vec![Mutation::new(MutationOperator::Gaussian { sigma: f32::NAN }, 1.0)];
+        rejects(&config, "mutations[0].sigma");
+        config.mutations = vec![Mutation::new(MutationOperator::Polynomial { eta: -2.0 }, 1.0)];
+        rejects(&config, "mutations[0].eta");
+
+        let mut config = SimulationConfig {
+            crossover: CrossoverOperator::SimulatedBinary { eta: -1.0 },
+            ..Default::default()
+        };
+        rejects(&config, "crossover.eta");
+        config.crossover = CrossoverOperator::BlendAlpha { alpha: -0.5 };
+        rejects(&config, "crossover.alpha");
+    }
+
+    // NaN compares false with everything, it mustn't slip through
+    #[test]
+    fn refuses_nan_parameters() {
+        let mut config = SimulationConfig {
+            niching: Niching::Sharing { radius: 2.0, alpha: f32::NAN },
+            ..Default::default()
+        };
+        rejects(&config, "niching.radius and alpha");
+        config.niching = Niching::Sharing { radius: f32::NAN, alpha: 1.0 };
+        rejects(&config, "niching.radius and alpha");
+        config.niching = Niching::Speciation { threshold: f32::NAN };
+        rejects(&config, "niching.threshold");
+        config.niching = Niching::None;
+        config.neighborhood = Neighborhood::Radius { radius: f32::NAN };
+        rejects(&config, "neighborhood.radius");
+        config.neighborhood = Neighborhood::Panmictic;
+        config.world_bounds = f32::NAN;
+        rejects(&config, "world_bounds");
+    }
+
+    #[test]
+    fn checks_island_overrides_like_the_base_settings() {
+        let mut config = SimulationConfig::default();
+        config.islands.count = 2;
+        config.islands.settings = vec![IslandSettings {
+            crossover: Some(CrossoverOperator::SimulatedBinary { eta: f32::NAN }),
+            ..Default::default()
+        }];
+        rejects(&config, "islands.settings[0].crossover.eta");
+
+        // A steady state replacing more than a small island holds
+        config.islands.settings = vec![IslandSettings {
+            population_size: Some(20),
+            ..Default::default()
+        }];
+        config.replacement = ReplacementModel::SteadyState { replaced: 50 };
+        rejects(&config, "islands.settings[0].population_size");
+        config.replacement = ReplacementModel::SteadyState { replaced: 10 };
+        config.validate().unwrap();
+    }
+}
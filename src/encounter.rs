@@ -0,0 +1,214 @@
+// Encounter mode, used with encounters.enabled: instead of breeding a whole
+// generation at once, two mature cubes whose boxes overlap may mate and their
+// child appears where they met. Works on the entity cubes and on the
+// instanced swarm alike. Acceptance rolls and children come from the run's
+// RNG, but which cubes meet depends on frame timing, so a seed doesn't
+// reproduce an encounter run.
+use bevy::prelude::*;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+use algogen::ga::SpatialHash;
+
+use crate::config::{EncounterConfig, SimulationConfig};
+use crate::instancing::CubeSwarm;
+use crate::simulation::{CorePopulation, ParentCube, RunSummary, SimRng, SimulationState};
+
+// Side of a parent cube, see spawn_parent_cube
+const CUBE_SIZE: f32 = 0.3;
+
+pub struct EncounterPlugin;
+
+impl Plugin for EncounterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            encounter_system.run_if(
+                resource_exists::<Encounters>
+                    .and_then(in_state(SimulationState::Running))
+                    .and_then(not(resource_exists::<RunSummary>)),
+            ),
+        );
+    }
+}
+
+// Only there in encounter mode
+#[derive(Resource)]
+pub struct Encounters {
+    pub settings: EncounterConfig,
+    // seconds of run time when every cube was first seen and last mated
+    born: HashMap<u64, f32>,
+    mated: HashMap<u64, f32>,
+    // pairs rolled and still overlapping last frame, a pair only gets one
+    // chance per contact
+    touching: HashSet<(u64, u64)>,
+    // matings since the run started, and the children that made it in
+    pub matings: u64,
+    pub births: u64,
+}
+
+impl Encounters {
+    pub fn new(settings: EncounterConfig) -> Self {
+        Encounters {
+            settings,
+            born: HashMap::new(),
+            mated: HashMap::new(),
+            touching: HashSet::new(),
+            matings: 0,
+            births: 0,
+        }
+    }
+
+    fn ready(&self, id: u64, now: f32) -> bool {
+        let mature = self.born.get(&id).is_some_and(|&born| now - born >= self.settings.maturity);
+        let rested = self.mated.get(&id).is_none_or(|&mated| now - mated >= self.settings.cooldown);
+        mature && rested
+    }
+
+    // A cube takes a mate with probability mate_fitness^choosiness
+    fn accepts(&self, mate_fitness: f32, rng: &mut impl Rng) -> bool {
+        rng.gen::<f32>() < mate_fitness.max(0.0).powf(self.settings.choosiness)
+    }
+
+    // Indices into `ready` of the pairs that touch and accept each other
+    // this frame. A cube mates at most once per frame and a pair is rolled
+    // once per contact, on the first frame neither of its cubes is busy.
+    fn pair_up(&mut self, ready: &[(u64, Vec3)], fitness: &HashMap<u64, f32>, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+        let positions: Vec<[f32; 3]> = ready.iter().map(|(_, position)| position.to_array()).collect();
+        let hash = SpatialHash::new(CUBE_SIZE, &positions);
+        let mut touching = HashSet::new();
+        let mut busy = vec![false; ready.len()];
+        let mut pairs = Vec::new();
+        for index in 0..ready.len() {
+            // Same size boxes overlap when their centers are closer than a
+            // side on every axis
+            for other in hash.neighbors(index, CUBE_SIZE, true) {
+                let (id1, id2) = (ready[index].0, ready[other].0);
+                let pair = (id1.min(id2), id1.max(id2));
+                if self.touching.contains(&pair) {
+                    touching.insert(pair);
+                    continue;
+                }
+                // neighbors gives every pair both ways round. A pair with a
+                // busy cube isn't rolled and gets its chance on a later frame.
+                if touching.contains(&pair) || busy[index] || busy[other] {
+                    continue;
+                }
+                let (Some(&fitness1), Some(&fitness2)) = (fitness.get(&id1), fitness.get(&id2)) else {
+                    continue;
+                };
+                touching.insert(pair);
+                if !self.accepts(fitness2, rng) || !self.accepts(fitness1, rng) {
+                    continue;
+                }
+                busy[index] = true;
+                busy[other] = true;
+                pairs.push((index, other));
+            }
+        }
+        self.touching = touching;
+        pairs
+    }
+}
+
+// Inserted with the first generation or a loaded checkpoint. Ages and
+// cooldowns start over, they aren't saved.
+pub(crate) fn insert_encounters(commands: &mut Commands, config: &SimulationConfig) {
+    if config.encounters.enabled {
+        commands.insert_resource(Encounters::new(config.encounters));
+    } else {
+        commands.remove_resource::<Encounters>();
+    }
+}
+
+fn encounter_system(
+    mut encounters: ResMut<Encounters>,
+    mut population: ResMut<CorePopulation>,
+    mut rng: ResMut<SimRng>,
+    time: Res<Time>,
+    cubes: Query<(&ParentCube, &Transform)>,
+    swarm: Res<CubeSwarm>,
+) {
+    let now = time.elapsed_seconds();
+    // Only one of the two render modes has cubes
+    let mut found: Vec<(u64, Vec3)> = cubes.iter().map(|(cube, transform)| (cube.id, transform.translation)).collect();
+    found.extend(swarm.ids.iter().copied().zip(swarm.positions.iter().copied()));
+
+    let encounters = &mut *encounters;
+    if population.is_changed() {
+        let alive: HashSet<u64> = found.iter().map(|&(id, _)| id).collect();
+        encounters.born.retain(|id, _| alive.contains(id));
+        encounters.mated.retain(|id, _| alive.contains(id));
+    }
+    for &(id, _) in found.iter() {
+        encounters.born.entry(id).or_insert(now);
+    }
+
+    let ready: Vec<(u64, Vec3)> = found.into_iter().filter(|&(id, _)| encounters.ready(id, now)).collect();
+    let fitness: HashMap<u64, f32> =
+        population.individuals.iter().map(|individual| (individual.id, individual.fitness)).collect();
+
+    let rng = &mut rng.rng;
+    for (index, other) in encounters.pair_up(&ready, &fitness, rng) {
+        let (id1, id2) = (ready[index].0, ready[other].0);
+        let meeting = (ready[index].1 + ready[other].1) / 2.0;
+        if population.mate(id1, id2, meeting.to_array(), rng).is_some() {
+            encounters.births += 1;
+        }
+        encounters.matings += 1;
+        encounters.mated.insert(id1, now);
+        encounters.mated.insert(id2, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algogen::ga::seeded_rng;
+
+    // A contact that is turned down isn't rolled again, neither the other
+    // way round on the same frame nor on the frames it keeps touching
+    #[test]
+    fn rejected_pair_rolled_once_per_contact() {
+        let mut encounters = Encounters::new(EncounterConfig::default());
+        let ready = [(1, Vec3::ZERO), (2, Vec3::splat(CUBE_SIZE / 2.0))];
+        // Nobody takes a mate of fitness 0 with choosiness 1
+        let fitness = HashMap::from([(1, 0.0), (2, 0.0)]);
+
+        let mut rng = seeded_rng(7);
+        let mut expected = seeded_rng(7);
+        expected.gen::<f32>();
+        assert!(encounters.pair_up(&ready, &fitness, &mut rng).is_empty());
+        assert_eq!(rng, expected);
+        assert!(encounters.pair_up(&ready, &fitness, &mut rng).is_empty());
+        assert_eq!(rng, expected);
+
+        // Parting and meeting again is a new contact
+        encounters.pair_up(&[ready[0]], &fitness, &mut rng);
+        encounters.pair_up(&ready, &fitness, &mut rng);
+        expected.gen::<f32>();
+        assert_eq!(rng, expected);
+    }
+
+    // Three cubes on top of each other: the pair left out because one of
+    // its cubes already mated is rolled on a later frame
+    #[test]
+    fn busy_pair_rolled_later() {
+        let mut encounters = Encounters::new(EncounterConfig::default());
+        let ready = [(1, Vec3::ZERO), (2, Vec3::splat(0.01)), (3, Vec3::splat(0.02))];
+        // Everybody takes a mate of fitness 1
+        let fitness = HashMap::from([(1, 1.0), (2, 1.0), (3, 1.0)]);
+        let mut rng = seeded_rng(7);
+
+        let mut mated = HashSet::new();
+        for _ in 0..3 {
+            let pairs = encounters.pair_up(&ready, &fitness, &mut rng);
+            assert_eq!(pairs.len(), 1);
+            let (index, other) = pairs[0];
+            mated.insert((ready[index].0.min(ready[other].0), ready[index].0.max(ready[other].0)));
+        }
+        assert_eq!(mated, HashSet::from([(1, 2), (1, 3), (2, 3)]));
+        // Every pair had its chance for this contact
+        assert!(encounters.pair_up(&ready, &fitness, &mut rng).is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+// Crossover operators. Every genome picks the operators that make sense for
+// its genes and falls back to a safe default for the others:
+// - slot operators work on any linear chromosome
+// - real operators need real valued genes
+// - permutation operators keep every allele exactly once
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CrossoverOperator {
+    #[default]
+    OnePoint,
+    TwoPoint,
+    Uniform,
+    // Weighted average of both parents
+    Arithmetic,
+    // BLX-alpha: children drawn around the parents' interval
+    BlendAlpha { alpha: f32 },
+    // Simulated binary crossover with distribution index eta
+    SimulatedBinary { eta: f32 },
+    // PMX
+    PartiallyMapped,
+    // OX
+    Order,
+    Cycle,
+}
+
+impl CrossoverOperator {
+    // One of each operator with default parameters, used by the UI
+    pub const ALL: [CrossoverOperator; 9] = [
+        CrossoverOperator::OnePoint,
+        CrossoverOperator::TwoPoint,
+        CrossoverOperator::Uniform,
+        CrossoverOperator::Arithmetic,
+        CrossoverOperator::BlendAlpha { alpha: 0.5 },
+        CrossoverOperator::SimulatedBinary { eta: 2.0 },
+        CrossoverOperator::PartiallyMapped,
+        CrossoverOperator::Order,
+        CrossoverOperator::Cycle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CrossoverOperator::OnePoint => "One-point",
+            CrossoverOperator::TwoPoint => "Two-point",
+            CrossoverOperator::Uniform => "Uniform",
+            CrossoverOperator::Arithmetic => "Arithmetic",
+            CrossoverOperator::BlendAlpha { .. } => "BLX-alpha",
+            CrossoverOperator::SimulatedBinary { .. } => "SBX",
+            CrossoverOperator::PartiallyMapped => "PMX",
+            CrossoverOperator::Order => "Order (OX)",
+            CrossoverOperator::Cycle => "Cycle",
+        }
+    }
+
+    pub fn same_kind(&self, other: &CrossoverOperator) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    pub fn is_real(&self) -> bool {
+        matches!(
+            self,
+            CrossoverOperator::Arithmetic
+                | CrossoverOperator::BlendAlpha { .. }
+                | CrossoverOperator::SimulatedBinary { .. }
+        )
+    }
+
+    // Slot recombination, non slot operators fall back to one-point.
+    // The second child takes every slot the first one didn't.
+    pub fn slots<T: Clone, R: Rng + ?Sized>(&self, a: &[T], b: &[T], rng: &mut R) -> (Vec<T>, Vec<T>) {
+        let len = a.len().min(b.len());
+        let mask: Vec<bool> = match self {
+            CrossoverOperator::TwoPoint => {
+                let (mut start, mut end) = (rng.gen_range(0..=len), rng.gen_range(0..=len));
+                if start > end {
+                    std::mem::swap(&mut start, &mut end);
+                }
+                (0..len).map(|i| !(start..end).contains(&i)).collect()
+            }
+            CrossoverOperator::Uniform => (0..len).map(|_| rng.gen_bool(0.5)).collect(),
+            _ => {
+                let cut = rng.gen_range(0..=len);
+                (0..len).map(|i| i < cut).collect()
+            }
+        };
+
+        let pick = |first: bool| -> Vec<T> {
+            mask.iter()
+                .enumerate()
+                .map(|(i, &from_a)| if from_a == first { a[i].clone() } else { b[i].clone() })
+                .collect()
+        };
+        (pick(true), pick(false))
+    }
+
+    // Real valued crossover, slot operators are applied per value and
+    // permutation operators fall back to arithmetic
+    pub fn real<R: Rng + ?Sized>(&self, a: &[f32], b: &[f32], rng: &mut R) -> (Vec<f32>, Vec<f32>) {
+        match *self {
+            CrossoverOperator::OnePoint | CrossoverOperator::TwoPoint | CrossoverOperator::Uniform => {
+                self.slots(a, b, rng)
+            }
+            CrossoverOperator::BlendAlpha { alpha } => {
+                let mut blend = |x: f32, y: f32| {
+                    let d = (x - y).abs();
+                    let (low, high) = (x.min(y) - alpha * d, x.max(y) + alpha * d);
+                    if high > low {
+                        rng.gen_range(low..=high)
+                    } else {
+                        x
+                    }
+                };
+                a.iter().zip(b).map(|(&x, &y)| (blend(x, y), blend(x, y))).unzip()
+            }
+            CrossoverOperator::SimulatedBinary { eta } => a
+                .iter()
+                .zip(b)
+                .map(|(&x, &y)| {
+                    let u: f32 = rng.gen();
+                    let beta = if u <= 0.5 {
+                        (2.0 * u).powf(1.0 / (eta + 1.0))
+                    } else {
+                        (1.0 / (2.0 * (1.0 - u))).powf(1.0 / (eta + 1.0))
+                    };
+                    (
+                        0.5 * ((1.0 + beta) * x + (1.0 - beta) * y),
+                        0.5 * ((1.0 - beta) * x + (1.0 + beta) * y),
+                    )
+                })
+                .unzip(),
+            _ => {
+                let weight: f32 = rng.gen();
+                a.iter()
+                    .zip(b)
+                    .map(|(&x, &y)| (x * weight + y * (1.0 - weight), y * weight + x * (1.0 - weight)))
+                    .unzip()
+            }
+        }
+    }
+
+    // Permutation crossover, any other operator falls back to OX
+    pub fn permutation<R: Rng + ?Sized>(&self, a: &[usize], b: &[usize], rng: &mut R) -> (Vec<usize>, Vec<usize>) {
+        let len = a.len();
+        if len < 2 || len != b.len() {
+            return (a.to_vec(), b.to_vec());
+        }
+
+        match self {
+            CrossoverOperator::Cycle => (cycle(a, b), cycle(b, a)),
+            _ => {
+                let (mut start, mut end) = (rng.gen_range(0..len), rng.gen_range(0..len));
+                if start > end {
+                    std::mem::swap(&mut start, &mut end);
+                }
+                if *self == CrossoverOperator::PartiallyMapped {
+                    (pmx(a, b, start, end), pmx(b, a, start, end))
+                } else {
+                    (order(a, b, start, end), order(b, a, start, end))
+                }
+            }
+        }
+    }
+}
+
+// Keeps a[start..=end], the rest is filled in b's order
+fn order(a: &[usize], b: &[usize], start: usize, end: usize) -> Vec<usize> {
+    let kept = &a[start..=end];
+    let mut rest = b.iter().filter(|gene| !kept.contains(gene));
+    (0..a.len())
+        .map(|i| if (start..=end).contains(&i) { a[i] } else { *rest.next().unwrap() })
+        .collect()
+}
+
+// Keeps a[start..=end], the rest comes from b following the mapping of the
+// swapped section
+fn pmx(a: &[usize], b: &[usize], start: usize, end: usize) -> Vec<usize> {
+    let section = start..=end;
+    let mut child = b.to_vec();
+    child[section.clone()].copy_from_slice(&a[section.clone()]);
+
+    for i in (0..a.len()).filter(|i| !section.contains(i)) {
+        let mut gene = b[i];
+        while let Some(j) = section.clone().find(|&j| a[j] == gene) {
+            gene = b[j];
+        }
+        child[i] = gene;
+    }
+    child
+}
+
+// Alternates whole cycles between the parents, starting with a
+fn cycle(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let len = a.len();
+    let mut child = vec![usize::MAX; len];
+    let mut from_a = true;
+
+    for start in 0..len {
+        if child[start] != usize::MAX {
+            continue;
+        }
+        let mut i = start;
+        loop {
+            child[i] = if from_a { a[i] } else { b[i] };
+            i = match a.iter().position(|&gene| gene == b[i]) {
+                Some(next) => next,
+                None => break,
+            };
+            if i == start {
+                break;
+            }
+        }
+        from_a = !from_a;
+    }
+    child
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::seeded_rng;
+    use rand::seq::SliceRandom;
+
+    fn is_permutation(child: &[usize], len: usize) -> bool {
+        let mut sorted = child.to_vec();
+        sorted.sort_unstable();
+        sorted == (0..len).collect::<Vec<_>>()
+    }
+
+    // Random parents of random lengths, every child must hold each gene once
+    fn check_permutations(operator: CrossoverOperator) {
+        let mut rng = seeded_rng(7);
+        for _ in 0..2000 {
+            let len = rng.gen_range(2..40);
+            let mut a: Vec<usize> = (0..len).collect();
+            let mut b = a.clone();
+            a.shuffle(&mut rng);
+            b.shuffle(&mut rng);
+            let (child1, child2) = operator.permutation(&a, &b, &mut rng);
+            assert!(is_permutation(&child1, len), "{operator:?} gave {child1:?} from {a:?} and {b:?}");
+            assert!(is_permutation(&child2, len), "{operator:?} gave {child2:?} from {a:?} and {b:?}");
+        }
+    }
+
+    #[test]
+    fn pmx_children_are_permutations() {
+        check_permutations(CrossoverOperator::PartiallyMapped);
+    }
+
+    #[test]
+    fn order_children_are_permutations() {
+        check_permutations(CrossoverOperator::Order);
+    }
+
+    #[test]
+    fn cycle_children_are_permutations() {
+        check_permutations(CrossoverOperator::Cycle);
+    }
+
+    // Cycle crossover keeps every gene at a position one of the parents had it
+    #[test]
+    fn cycle_genes_come_from_a_parent_slot() {
+        let mut rng = seeded_rng(11);
+        for _ in 0..500 {
+            let mut a: Vec<usize> = (0..20).collect();
+            let mut b = a.clone();
+            a.shuffle(&mut rng);
+            b.shuffle(&mut rng);
+            let (child, _) = CrossoverOperator::Cycle.permutation(&a, &b, &mut rng);
+            assert!(child.iter().enumerate().all(|(i, &gene)| gene == a[i] || gene == b[i]));
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+
+use super::fitness_cache::genome_hash;
+use super::genome::{group_color, CubeGenome, YELLOW};
+
+// Scores a genome, higher is better
+pub trait FitnessFunction<G>: Send + Sync {
+    fn evaluate(&self, genome: &G) -> f32;
+
+    // Genomes with the same key must get the same score, their score is
+    // then computed once and cached. None, the default, never caches.
+    fn cache_key(&self, _genome: &G) -> Option<u64> {
+        None
+    }
+}
+
+// Plain functions and closures work as fitness functions. They may look at
+// anything, the position included, so their scores aren't cached.
+impl<G, F: Fn(&G) -> f32 + Send + Sync> FitnessFunction<G> for F {
+    fn evaluate(&self, genome: &G) -> f32 {
+        self(genome)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorMetric {
+    #[default]
+    RgbEuclidean,
+    // CIELAB Delta-E 2000
+    DeltaE2000,
+    HueAngle,
+}
+
+impl ColorMetric {
+    pub const ALL: [ColorMetric; 3] = [ColorMetric::RgbEuclidean, ColorMetric::DeltaE2000, ColorMetric::HueAngle];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMetric::RgbEuclidean => "RGB Euclidean",
+            ColorMetric::DeltaE2000 => "CIELAB Delta-E 2000",
+            ColorMetric::HueAngle => "Hue angle",
+        }
+    }
+
+    // Distance between two srgb colors scaled to [0, 1]
+    pub fn distance(&self, color: [f32; 3], target: [f32; 3]) -> f32 {
+        let distance = match self {
+            ColorMetric::RgbEuclidean => {
+                let (c1, c2) = (to_linear(color), to_linear(target));
+                (0..3).map(|i| (c1[i] - c2[i]).powi(2)).sum::<f32>().sqrt() / 3f32.sqrt()
+            }
+            ColorMetric::DeltaE2000 => delta_e_2000(to_lab(color), to_lab(target)) / 100.0,
+            // Greys have no hue: a grey is as far as it gets from any
+            // colored target and matches a grey one
+            ColorMetric::HueAngle => match (hue(color), hue(target)) {
+                (Some(hue1), Some(hue2)) => {
+                    let difference = (hue1 - hue2).abs();
+                    difference.min(360.0 - difference) / 180.0
+                }
+                (None, None) => 0.0,
+                _ => 1.0,
+            },
+        };
+        distance.clamp(0.0, 1.0)
+    }
+}
+
+// Scores the whole chromosome: mean similarity of every gene color to the
+// target, 1.0 when all genes match it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorFitness {
+    // srgb
+    pub target: [f32; 3],
+    pub metric: ColorMetric,
+}
+
+impl Default for ColorFitness {
+    fn default() -> Self {
+        ColorFitness {
+            target: group_color(YELLOW),
+            metric: ColorMetric::default(),
+        }
+    }
+}
+
+impl FitnessFunction<CubeGenome> for ColorFitness {
+    fn evaluate(&self, genome: &CubeGenome) -> f32 {
+        let total: f32 = genome
+            .genes
+            .iter()
+            .map(|&gene| 1.0 - self.metric.distance(group_color(gene), self.target))
+            .sum();
+        total / genome.genes.len() as f32
+    }
+
+    // Only the colors count, clones anywhere in the world score the same
+    fn cache_key(&self, genome: &CubeGenome) -> Option<u64> {
+        Some(genome_hash(genome))
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_linear(color: [f32; 3]) -> [f32; 3] {
+    color.map(srgb_to_linear)
+}
+
+// srgb to CIELAB with a D65 white point
+fn to_lab(color: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = to_linear(color);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn delta_e_2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let [l1, a1, b1] = lab1.map(f64::from);
+    let [l2, a2, b2] = lab2.map(f64::from);
+
+    let c_mean = ((a1.hypot(b1)) + (a2.hypot(b2))) / 2.0;
+    let g = 0.5 * (1.0 - (c_mean.powi(7) / (c_mean.powi(7) + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 <= h1 {
+        h2 - h1 + 360.0
+    } else {
+        h2 - h1 - 360.0
+    };
+    let delta_big_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_mean - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_mean).to_radians().cos()
+        + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_mean.powi(7) / (c_mean.powi(7) + 25f64.powi(7))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_big_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt() as f32
+}
+
+// HSV hue in degrees, None for greys
+fn hue(color: [f32; 3]) -> Option<f32> {
+    let [r, g, b] = color;
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    if delta == 0.0 {
+        return None;
+    }
+
+    let hue = if max == r {
+        60.0 * ((g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    Some(hue.rem_euclid(360.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pairs from Sharma, Wu and Dalal, "The CIEDE2000 color-difference
+    // formula: implementation notes, supplementary test data and
+    // mathematical observations" (2005)
+    #[test]
+    fn delta_e_2000_matches_sharma_pairs() {
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+            ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+        ];
+        for (lab1, lab2, expected) in pairs {
+            let distance = delta_e_2000(lab1, lab2);
+            assert!((distance - expected).abs() < 1e-3, "{lab1:?} {lab2:?}: {distance} instead of {expected}");
+            assert!((delta_e_2000(lab2, lab1) - distance).abs() < 1e-4);
+        }
+    }
+
+    // White carries no hue, it mustn't score like red
+    #[test]
+    fn hue_angle_keeps_greys_apart() {
+        let (red, white) = (group_color(0), group_color(4));
+        let metric = ColorMetric::HueAngle;
+        assert_eq!(metric.distance(red, red), 0.0);
+        assert_eq!(metric.distance(white, red), 1.0);
+        assert_eq!(metric.distance(red, white), 1.0);
+        assert_eq!(metric.distance(white, [0.5, 0.5, 0.5]), 0.0);
+    }
+}
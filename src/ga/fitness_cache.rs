@@ -0,0 +1,163 @@
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+use super::fitness::FitnessFunction;
+use super::genome::Genome;
+
+// 64-bit FNV-1a. Unlike DefaultHasher its output is fixed, so the same genes
+// hash the same on every run and Rust version.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Hash of the genes hash_genes feeds, the cache key of the genome's own
+// fitness functions. Collisions aren't checked: two
+// different genomes with the same hash would share a cached score, which
+// 64 bits make unlikely enough for populations of this size.
+pub fn genome_hash<G: Genome>(genome: &G) -> u64 {
+    let mut hasher = Fnv1a::default();
+    genome.hash_genes(&mut hasher);
+    hasher.finish()
+}
+
+// Scores of genomes already evaluated, keyed by the fitness function's
+// cache_key, so unchanged individuals and clones aren't scored again.
+// Functions without a key are always called. Fitness functions are expected
+// to be deterministic. Once `capacity` scores are stored the cache starts
+// over, a capacity of 0 turns it off.
+#[derive(Debug, Clone, Default)]
+pub struct FitnessCache {
+    pub capacity: usize,
+    scores: HashMap<u64, f32>,
+    // lookups since the cache was created or cleared
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl FitnessCache {
+    pub fn new(capacity: usize) -> Self {
+        FitnessCache {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    // Share of lookups answered from the cache
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f32 / lookups as f32
+        }
+    }
+
+    // Needed whenever the fitness function changes
+    pub fn clear(&mut self) {
+        self.scores.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    // Scores every genome, in parallel. Cached scores are reused and new ones
+    // stored once the whole batch is done.
+    pub fn evaluate<G: Genome>(&mut self, fitness: &dyn FitnessFunction<G>, genomes: &[&G]) -> Vec<f32> {
+        if self.capacity == 0 {
+            return genomes.par_iter().map(|genome| fitness.evaluate(genome)).collect();
+        }
+
+        let scores = &self.scores;
+        let looked_up: Vec<(Option<u64>, f32, bool)> = genomes
+            .par_iter()
+            .map(|genome| match fitness.cache_key(genome) {
+                Some(key) => match scores.get(&key) {
+                    Some(&score) => (Some(key), score, true),
+                    None => (Some(key), fitness.evaluate(genome), false),
+                },
+                None => (None, fitness.evaluate(genome), false),
+            })
+            .collect();
+
+        let mut result = Vec::with_capacity(looked_up.len());
+        for (key, score, hit) in looked_up {
+            if hit {
+                self.hits += 1;
+            } else if let Some(key) = key {
+                self.misses += 1;
+                if self.scores.len() >= self.capacity {
+                    self.scores.clear();
+                }
+                self.scores.insert(key, score);
+            }
+            result.push(score);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::{ColorFitness, CubeGenome};
+
+    fn cube(genes: [u8; 5], position: [f32; 3]) -> CubeGenome {
+        CubeGenome {
+            color_group: 0,
+            position,
+            genes,
+        }
+    }
+
+    // Clones anywhere in the world share one ColorFitness score, other
+    // genes miss
+    #[test]
+    fn color_fitness_hits_on_clones() {
+        let mut cache = FitnessCache::new(100);
+        let (a, b, c) = (cube([1; 5], [0.0; 3]), cube([1; 5], [2.0; 3]), cube([2; 5], [0.0; 3]));
+        let first = cache.evaluate(&ColorFitness::default(), &[&a, &c]);
+        assert_eq!((cache.hits, cache.misses), (0, 2));
+        let second = cache.evaluate(&ColorFitness::default(), &[&b, &c]);
+        assert_eq!((cache.hits, cache.misses), (2, 2));
+        assert_eq!(first, second);
+        assert_eq!(cache.len(), 2);
+    }
+
+    // A closure may score the position, clones at different places must
+    // not get each other's score
+    #[test]
+    fn closures_are_not_cached() {
+        let mut cache = FitnessCache::new(100);
+        let height = |genome: &CubeGenome| genome.position[1];
+        let (a, b) = (cube([1; 5], [0.0; 3]), cube([1; 5], [1.0; 3]));
+        let scores = cache.evaluate(&height, &[&a, &b, &a]);
+        assert_eq!(scores, vec![0.0, 1.0, 0.0]);
+        assert_eq!((cache.hits, cache.misses), (0, 0));
+        assert!(cache.is_empty());
+    }
+}
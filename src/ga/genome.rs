@@ -0,0 +1,198 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+use super::crossover::CrossoverOperator;
+use super::mutation::{Mutation, MutationOperator};
+
+pub const GENE_COUNT: usize = 5;
+pub const COLOR_GROUPS: u8 = 5;
+pub const YELLOW: u8 = 3;
+
+// Anything the selection/reproduction pipeline can evolve.
+// Params carries whatever a representation needs to build and mutate
+// genomes (length, allele bounds, ...).
+// Send + Sync so children can be bred in parallel
+pub trait Genome: Clone + Debug + Send + Sync {
+    type Params: Clone + Debug + Default + Send + Sync;
+
+    fn random<R: Rng + ?Sized>(params: &Self::Params, rng: &mut R) -> Self;
+
+    // Produces two children, the second one being the complement of the first
+    fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Self,
+        operator: &CrossoverOperator,
+        params: &Self::Params,
+        rng: &mut R,
+    ) -> (Self, Self);
+
+    fn mutate<R: Rng + ?Sized>(
+        &mut self,
+        mutation: &Mutation,
+        params: &Self::Params,
+        generation: u32,
+        rng: &mut R,
+    );
+
+    // How different two genomes are, 0.0 meaning identical
+    fn distance(&self, other: &Self) -> f32;
+
+    // Feeds the genes the genome's own fitness functions depend on to the
+    // hasher, genomes that must score the same must hash the same. Those
+    // functions key the fitness cache with it, see FitnessFunction::cache_key,
+    // so leaving out what they ignore lets clones share a score.
+    fn hash_genes<H: Hasher>(&self, state: &mut H);
+
+    // Where the individual lives for spatial mating, genomes without a
+    // position all share the origin
+    fn position(&self) -> Option<[f32; 3]> {
+        None
+    }
+
+    fn set_position(&mut self, _position: [f32; 3]) {}
+}
+
+// srgb color of each color group
+pub fn group_color(color_group: u8) -> [f32; 3] {
+    match color_group {
+        0 => [1.0, 0.0, 0.0], // Red
+        1 => [0.0, 1.0, 0.0], // Green
+        2 => [0.0, 0.0, 1.0], // Blue
+        3 => [1.0, 1.0, 0.0], // Yellow
+        _ => [1.0, 1.0, 1.0], // Default White
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CubeParams {
+    // random genomes are placed inside [spawn_min, spawn_max)
+    pub spawn_min: [f32; 3],
+    pub spawn_max: [f32; 3],
+    // positions are mutated inside [-bounds, bounds]
+    pub bounds: f32,
+}
+
+impl Default for CubeParams {
+    fn default() -> Self {
+        CubeParams {
+            spawn_min: [-3.0, -5.0, -3.0],
+            spawn_max: [7.0, 7.0, 7.0],
+            bounds: 9.0,
+        }
+    }
+}
+
+// The cube simulation genome: the parent's own color group and position,
+// plus the chromosome shown as the string of child cubes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CubeGenome {
+    pub color_group: u8,
+    pub position: [f32; 3],
+    pub genes: [u8; GENE_COUNT],
+}
+
+impl Genome for CubeGenome {
+    type Params = CubeParams;
+
+    fn random<R: Rng + ?Sized>(params: &CubeParams, rng: &mut R) -> Self {
+        let position = std::array::from_fn(|i| rng.gen_range(params.spawn_min[i]..params.spawn_max[i]));
+        let color_group = rng.gen_range(0..=COLOR_GROUPS);
+        let genes = std::array::from_fn(|_| rng.gen_range(0..COLOR_GROUPS));
+
+        CubeGenome {
+            color_group,
+            position,
+            genes,
+        }
+    }
+
+    // The chromosome always uses slot recombination, the position follows
+    // the operator when it is a real valued one and is blended otherwise
+    fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &CubeGenome,
+        operator: &CrossoverOperator,
+        _: &CubeParams,
+        rng: &mut R,
+    ) -> (CubeGenome, CubeGenome) {
+        let (genes1, genes2) = operator.slots(&self.genes, &other.genes, rng);
+
+        let position_operator = if operator.is_real() { *operator } else { CrossoverOperator::Arithmetic };
+        let (position1, position2) = position_operator.real(&self.position, &other.position, rng);
+
+        // The parent cube color is inherited from either parent
+        let (color1, color2) = if rng.gen_bool(0.5) {
+            (self.color_group, other.color_group)
+        } else {
+            (other.color_group, self.color_group)
+        };
+
+        let child = |color_group: u8, position: Vec<f32>, genes: Vec<u8>| CubeGenome {
+            color_group,
+            position: std::array::from_fn(|i| position[i]),
+            genes: std::array::from_fn(|i| genes[i]),
+        };
+        (child(color1, position1, genes1), child(color2, position2, genes2))
+    }
+
+    // Real valued operators only move the position, which ColorFitness
+    // doesn't score, so they never evolve a color. The others act on the
+    // parent color and the chromosome.
+    fn mutate<R: Rng + ?Sized>(&mut self, mutation: &Mutation, params: &CubeParams, generation: u32, rng: &mut R) {
+        match mutation.operator {
+            MutationOperator::YellowBias { growth } => {
+                let chance = (mutation.rate + growth * generation as f32).min(1.0);
+                for gene in std::iter::once(&mut self.color_group).chain(self.genes.iter_mut()) {
+                    if rng.gen::<f32>() < chance {
+                        *gene = YELLOW;
+                    }
+                }
+            }
+            MutationOperator::Creep { .. } | MutationOperator::Gaussian { .. } | MutationOperator::Polynomial { .. } => {
+                mutation.real(&mut self.position, -params.bounds, params.bounds, rng);
+            }
+            // Colors aren't bits, a flipped gene takes any other color
+            MutationOperator::BitFlip => {
+                let flip = |gene: &mut u8, alleles: u8, rng: &mut R| {
+                    if rng.gen::<f32>() < mutation.rate {
+                        *gene = (*gene + rng.gen_range(1..alleles)) % alleles;
+                    }
+                };
+                flip(&mut self.color_group, COLOR_GROUPS + 1, rng);
+                for gene in self.genes.iter_mut() {
+                    flip(gene, COLOR_GROUPS, rng);
+                }
+            }
+            _ => {
+                mutation.discrete(std::slice::from_mut(&mut self.color_group), rng, |rng| {
+                    rng.gen_range(0..=COLOR_GROUPS)
+                });
+                mutation.discrete(&mut self.genes, rng, |rng| rng.gen_range(0..COLOR_GROUPS));
+            }
+        }
+    }
+
+    // Number of color genes that differ, the position is not part of it
+    fn distance(&self, other: &CubeGenome) -> f32 {
+        let parent = (self.color_group != other.color_group) as usize;
+        let genes = self.genes.iter().zip(&other.genes).filter(|(a, b)| a != b).count();
+        (parent + genes) as f32
+    }
+
+    // The position moves with every Creep mutation but never changes the
+    // color ColorFitness scores, it's left out like in distance
+    fn hash_genes<H: Hasher>(&self, state: &mut H) {
+        self.color_group.hash(state);
+        self.genes.hash(state);
+    }
+
+    fn position(&self) -> Option<[f32; 3]> {
+        Some(self.position)
+    }
+
+    fn set_position(&mut self, position: [f32; 3]) {
+        self.position = position;
+    }
+}
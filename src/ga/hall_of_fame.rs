@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::fitness::FitnessFunction;
+use super::genome::{CubeGenome, Genome};
+use super::population::Population;
+
+// One of the best individuals ever seen and the generation it appeared in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fame<G = CubeGenome> {
+    pub id: u64,
+    pub generation: u32,
+    pub fitness: f32,
+    pub genome: G,
+}
+
+// The `capacity` best individuals of the whole run, best first. Every
+// individual gets in at most once, with the fitness it had when it first
+// made the list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HallOfFame<G = CubeGenome> {
+    pub capacity: usize,
+    pub entries: Vec<Fame<G>>,
+}
+
+impl<G: Genome> HallOfFame<G> {
+    pub fn new(capacity: usize) -> Self {
+        HallOfFame {
+            capacity,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn best(&self) -> Option<&Fame<G>> {
+        self.entries.first()
+    }
+
+    // Looks at the individuals and the latest offspring of the population
+    pub fn update(&mut self, population: &Population<G>) {
+        let worst = |entries: &[Fame<G>]| entries.last().map_or(f32::NEG_INFINITY, |fame| fame.fitness);
+        for individual in population.individuals.iter().chain(population.offspring.iter()) {
+            if self.entries.len() >= self.capacity && individual.fitness <= worst(&self.entries) {
+                continue;
+            }
+            if self.entries.iter().any(|fame| fame.id == individual.id) {
+                continue;
+            }
+            let at = self.entries.partition_point(|fame| fame.fitness >= individual.fitness);
+            self.entries.insert(
+                at,
+                Fame {
+                    id: individual.id,
+                    generation: population.generation,
+                    fitness: individual.fitness,
+                    genome: individual.genome.clone(),
+                },
+            );
+            self.entries.truncate(self.capacity);
+        }
+    }
+
+    // Scores the entries again with a new fitness function, for when the
+    // target changes mid-run, so they compare with the new population
+    pub fn rescore(&mut self, fitness: &dyn FitnessFunction<G>) {
+        for fame in self.entries.iter_mut() {
+            fame.fitness = fitness.evaluate(&fame.genome);
+        }
+        self.entries.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+    }
+}
+
+// One exported line, with the seed of the run like the stats history
+#[derive(Serialize)]
+struct Row<'a> {
+    seed: u64,
+    #[serde(flatten)]
+    fame: &'a Fame,
+}
+
+// `seed` is the seed of the run, the hall of fame doesn't keep it
+impl HallOfFame<CubeGenome> {
+    pub fn write_csv(&self, path: &Path, seed: u64) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "seed,rank,id,generation,fitness,color_group,genes")?;
+        for (rank, fame) in self.entries.iter().enumerate() {
+            let genes: Vec<String> = fame.genome.genes.iter().map(|gene| gene.to_string()).collect();
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{}",
+                seed,
+                rank + 1,
+                fame.id,
+                fame.generation,
+                fame.fitness,
+                fame.genome.color_group,
+                genes.join(" ")
+            )?;
+        }
+        file.flush()
+    }
+
+    pub fn write_jsonl(&self, path: &Path, seed: u64) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        for fame in self.entries.iter() {
+            serde_json::to_writer(&mut file, &Row { seed, fame })?;
+            writeln!(file)?;
+        }
+        file.flush()
+    }
+
+    // JSON Lines when the extension is .jsonl, CSV otherwise
+    pub fn write(&self, path: &Path, seed: u64) -> std::io::Result<()> {
+        if path.extension().is_some_and(|extension| extension == "jsonl") {
+            self.write_jsonl(path, seed)
+        } else {
+            self.write_csv(path, seed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::{seeded_rng, ColorFitness, ColorMetric, Individual};
+
+    fn population(fitness: &[f32], generation: u32) -> Population<CubeGenome> {
+        let mut population = Population::random_cubes(fitness.len(), &mut seeded_rng(4));
+        for (individual, &value) in population.individuals.iter_mut().zip(fitness) {
+            individual.fitness = value;
+        }
+        population.generation = generation;
+        population
+    }
+
+    fn ids(hall: &HallOfFame) -> Vec<u64> {
+        hall.entries.iter().map(|fame| fame.id).collect()
+    }
+
+    #[test]
+    fn keeps_the_best_in_order_up_to_capacity() {
+        let mut hall = HallOfFame::new(3);
+        hall.update(&population(&[0.2, 0.9, 0.5, 0.7, 0.1], 0));
+        let fitness: Vec<f32> = hall.entries.iter().map(|fame| fame.fitness).collect();
+        assert_eq!(fitness, [0.9, 0.7, 0.5]);
+        assert_eq!(hall.best().unwrap().fitness, 0.9);
+
+        let mut hall = HallOfFame::new(10);
+        hall.update(&population(&[0.2, 0.9], 0));
+        assert_eq!(hall.entries.len(), 2);
+    }
+
+    #[test]
+    fn offspring_count_and_ties_keep_the_older() {
+        let mut population = population(&[0.4, 0.6], 0);
+        let offspring = Individual {
+            id: 99,
+            genome: population.individuals[0].genome.clone(),
+            fitness: 0.8,
+        };
+        population.offspring.push(offspring);
+        let mut hall = HallOfFame::new(2);
+        hall.update(&population);
+        assert_eq!(ids(&hall), [99, population.individuals[1].id]);
+
+        // Equal to the worst entry of a full hall isn't enough to get in
+        population.individuals[0].fitness = 0.6;
+        population.offspring.clear();
+        hall.update(&population);
+        assert_eq!(ids(&hall), [99, population.individuals[1].id]);
+    }
+
+    #[test]
+    fn elites_get_in_once_with_their_first_generation() {
+        let mut population = population(&[0.3, 0.9, 0.5], 0);
+        let mut hall = HallOfFame::new(5);
+        hall.update(&population);
+
+        // The elite carried over is the same individual, even if re-scored
+        population.generation = 1;
+        population.individuals[1].fitness = 0.95;
+        hall.update(&population);
+        assert_eq!(hall.entries.len(), 3);
+        let elite = hall.best().unwrap();
+        assert_eq!((elite.id, elite.generation, elite.fitness), (population.individuals[1].id, 0, 0.9));
+    }
+
+    #[test]
+    fn rescore_sorts_by_the_new_target() {
+        let mut population = population(&[0.0; 6], 0);
+        let red = ColorFitness {
+            target: [1.0, 0.0, 0.0],
+            metric: ColorMetric::RgbEuclidean,
+        };
+        population.set_fitness(red);
+        let mut hall = HallOfFame::new(4);
+        hall.update(&population);
+
+        let blue = ColorFitness {
+            target: [0.0, 0.0, 1.0],
+            ..red
+        };
+        hall.rescore(&blue);
+        for fame in hall.entries.iter() {
+            assert_eq!(fame.fitness, blue.evaluate(&fame.genome));
+        }
+        assert!(hall.entries.windows(2).all(|pair| pair[0].fitness >= pair[1].fitness));
+    }
+}
@@ -0,0 +1,459 @@
+// Island model: several sub-populations evolving on their own, trading a few
+// individuals every now and then.
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::crossover::CrossoverOperator;
+use super::genome::{CubeGenome, Genome};
+use super::mutation::Mutation;
+use super::population::{Individual, Population, PopulationState};
+use super::seeded_rng;
+use super::selection::SelectionStrategy;
+
+// Which islands send migrants to which
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MigrationTopology {
+    // island i sends to island i + 1, the last one to the first
+    #[default]
+    Ring,
+    // every island sends to every other one
+    FullyConnected,
+    // every island sends to one other island picked at random each time
+    Random,
+}
+
+impl MigrationTopology {
+    pub const ALL: [MigrationTopology; 3] =
+        [MigrationTopology::Ring, MigrationTopology::FullyConnected, MigrationTopology::Random];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MigrationTopology::Ring => "Ring",
+            MigrationTopology::FullyConnected => "Fully connected",
+            MigrationTopology::Random => "Random",
+        }
+    }
+}
+
+// Which individuals leave their island
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MigrantPolicy {
+    #[default]
+    Best,
+    Random,
+}
+
+impl MigrantPolicy {
+    pub const ALL: [MigrantPolicy; 2] = [MigrantPolicy::Best, MigrantPolicy::Random];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MigrantPolicy::Best => "Best",
+            MigrantPolicy::Random => "Random",
+        }
+    }
+}
+
+// Migrants move to the islands they are sent to, where they replace the
+// worst individuals
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Migration {
+    pub topology: MigrationTopology,
+    // generations between two migrations
+    pub interval: u32,
+    // individuals sent to each destination
+    pub migrants: usize,
+    pub policy: MigrantPolicy,
+}
+
+impl Default for Migration {
+    fn default() -> Self {
+        Migration {
+            topology: MigrationTopology::default(),
+            interval: 10,
+            migrants: 2,
+            policy: MigrantPolicy::default(),
+        }
+    }
+}
+
+// Settings of one island that differ from the ones all islands share
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IslandSettings {
+    pub population_size: Option<usize>,
+    pub selection: Option<SelectionStrategy>,
+    pub crossover: Option<CrossoverOperator>,
+    pub crossover_rate: Option<f32>,
+    pub mutations: Option<Vec<Mutation>>,
+}
+
+impl IslandSettings {
+    pub fn apply<G: Genome>(&self, population: &mut Population<G>) {
+        if let Some(size) = self.population_size {
+            population.size = size;
+        }
+        if let Some(selection) = self.selection {
+            population.selection = selection;
+        }
+        if let Some(crossover) = self.crossover {
+            population.crossover = crossover;
+        }
+        if let Some(rate) = self.crossover_rate {
+            population.crossover_rate = rate;
+        }
+        if let Some(mutations) = &self.mutations {
+            population.mutations = mutations.clone();
+        }
+    }
+}
+
+// An individual that changed island during the last migration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Migrant {
+    pub id: u64,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Clone)]
+pub struct Archipelago<G: Genome = CubeGenome> {
+    pub islands: Vec<Population<G>>,
+    // island i uses settings[i] when there is one
+    pub settings: Vec<IslandSettings>,
+    pub migration: Migration,
+    pub migrants: Vec<Migrant>,
+}
+
+// What a checkpoint needs to rebuild an archipelago
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchipelagoState<G> {
+    pub islands: Vec<PopulationState<G>>,
+    pub migrants: Vec<Migrant>,
+}
+
+impl<G: Genome> Archipelago<G> {
+    // `count` random islands, each with the settings of `base` plus its own.
+    // Island i numbers its individuals i, i + count, i + 2 * count...
+    pub fn new<R: Rng + ?Sized>(
+        base: &Population<G>,
+        count: usize,
+        settings: Vec<IslandSettings>,
+        migration: Migration,
+        rng: &mut R,
+    ) -> Self {
+        let mut archipelago = Archipelago::empty(base, count, settings, migration);
+        for island in archipelago.islands.iter_mut() {
+            island.fill_random(rng);
+        }
+        archipelago
+    }
+
+    pub fn restore(
+        base: &Population<G>,
+        settings: Vec<IslandSettings>,
+        migration: Migration,
+        state: ArchipelagoState<G>,
+    ) -> Self {
+        let mut archipelago = Archipelago::empty(base, state.islands.len(), settings, migration);
+        for (island, state) in archipelago.islands.iter_mut().zip(state.islands) {
+            island.restore(state);
+        }
+        archipelago.migrants = state.migrants;
+        archipelago
+    }
+
+    fn empty(base: &Population<G>, count: usize, settings: Vec<IslandSettings>, migration: Migration) -> Self {
+        let mut islands = Vec::with_capacity(count);
+        for index in 0..count {
+            let mut island = base.clone();
+            island.individuals.clear();
+            island.offspring.clear();
+            island.cache.clear();
+            island.number_ids(index as u64, count as u64);
+            islands.push(island);
+        }
+        let mut archipelago = Archipelago {
+            islands,
+            settings,
+            migration,
+            migrants: Vec::new(),
+        };
+        archipelago.configure(base);
+        archipelago
+    }
+
+    pub fn state(&self) -> ArchipelagoState<G> {
+        ArchipelagoState {
+            islands: self.islands.iter().map(|island| island.state()).collect(),
+            migrants: self.migrants.clone(),
+        }
+    }
+
+    // Every island takes the settings of `base`, then its own on top
+    pub fn configure(&mut self, base: &Population<G>) {
+        for (index, island) in self.islands.iter_mut().enumerate() {
+            island.configure_from(base);
+            if let Some(settings) = self.settings.get(index) {
+                settings.apply(island);
+            }
+        }
+    }
+
+    // One generation on every island, in parallel, then a migration when
+    // one is due. Each island gets its own RNG seeded from `rng`.
+    pub fn step<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let seeds: Vec<u64> = self.islands.iter().map(|_| rng.gen()).collect();
+        self.islands.par_iter_mut().zip(seeds).for_each(|(island, seed)| {
+            island.step(&mut seeded_rng(seed));
+        });
+
+        // An extinct island doesn't step, it keeps the same generation
+        // number as the others in case migrants bring it back
+        let generation = self.islands.iter().map(|island| island.generation).max().unwrap_or_default();
+        for island in self.islands.iter_mut() {
+            if island.individuals.is_empty() {
+                island.offspring.clear();
+                island.births = 0;
+                island.deaths = 0;
+            }
+            island.generation = generation;
+        }
+
+        self.migrants.clear();
+        if self.migration.interval > 0 && generation.is_multiple_of(self.migration.interval) {
+            self.migrate(rng);
+        }
+    }
+
+    // Settings from `population`, one generation, and the result written back
+    // to `population` as the union of all islands
+    pub fn evolve<R: Rng + ?Sized>(&mut self, population: &mut Population<G>, rng: &mut R) {
+        self.configure(population);
+        self.step(rng);
+        self.merge_into(population);
+    }
+
+    pub fn cull(&mut self, population: &mut Population<G>, min_fitness: f32) {
+        for island in self.islands.iter_mut() {
+            island.cull(min_fitness);
+        }
+        self.merge_into(population);
+    }
+
+    // Every island's individuals and offspring in one population, so stats,
+    // hall of fame and the scene don't need to know about islands
+    pub fn merge_into(&self, population: &mut Population<G>) {
+        population.individuals = self.islands.iter().flat_map(|island| island.individuals.iter().cloned()).collect();
+        population.offspring = self.islands.iter().flat_map(|island| island.offspring.iter().cloned()).collect();
+        population.generation = self.islands.first().map_or(population.generation, |island| island.generation);
+        population.births = self.islands.iter().map(|island| island.births).sum();
+        population.deaths = self.islands.iter().map(|island| island.deaths).sum();
+        // Islands number their species apart, see number_ids
+        population.species = self.islands.iter().flat_map(|island| island.species.iter().cloned()).collect();
+    }
+
+    // Island of every individual, by id
+    pub fn island_of(&self) -> HashMap<u64, usize> {
+        self.islands
+            .iter()
+            .enumerate()
+            .flat_map(|(index, island)| island.individuals.iter().map(move |individual| (individual.id, index)))
+            .collect()
+    }
+
+    fn migrate<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let count = self.islands.len();
+        if count < 2 {
+            return;
+        }
+
+        // Everyone leaving is picked before anyone moves
+        let mut moves: Vec<Migrant> = Vec::new();
+        for from in 0..count {
+            let destinations: Vec<usize> = match self.migration.topology {
+                MigrationTopology::Ring => vec![(from + 1) % count],
+                MigrationTopology::FullyConnected => (0..count).filter(|&to| to != from).collect(),
+                MigrationTopology::Random => {
+                    let to = rng.gen_range(0..count - 1);
+                    vec![if to >= from { to + 1 } else { to }]
+                }
+            };
+            let ids = self.pick_migrants(from, self.migration.migrants * destinations.len(), rng);
+            for (slot, id) in ids.into_iter().enumerate() {
+                let to = destinations[slot % destinations.len()];
+                moves.push(Migrant { id, from, to });
+            }
+        }
+
+        let mut arrivals: Vec<Vec<Individual<G>>> = vec![Vec::new(); count];
+        for (from, island) in self.islands.iter_mut().enumerate() {
+            let leaving: HashMap<u64, usize> =
+                moves.iter().filter(|migrant| migrant.from == from).map(|migrant| (migrant.id, migrant.to)).collect();
+            island.individuals.retain(|individual| match leaving.get(&individual.id) {
+                Some(&to) => {
+                    arrivals[to].push(individual.clone());
+                    false
+                }
+                None => true,
+            });
+        }
+        for (island, arrivals) in self.islands.iter_mut().zip(arrivals) {
+            if arrivals.is_empty() {
+                continue;
+            }
+            island.individuals.extend(arrivals);
+            island.individuals.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+            let before = island.individuals.len();
+            island.individuals.truncate(island.size);
+            island.deaths += before - island.individuals.len();
+        }
+        self.migrants = moves;
+    }
+
+    // Ids of up to `wanted` individuals of an island, always leaving one behind
+    fn pick_migrants<R: Rng + ?Sized>(&self, island: usize, wanted: usize, rng: &mut R) -> Vec<u64> {
+        let individuals = &self.islands[island].individuals;
+        let wanted = wanted.min(individuals.len().saturating_sub(1));
+        let mut candidates: Vec<&Individual<G>> = individuals.iter().collect();
+        match self.migration.policy {
+            MigrantPolicy::Best => candidates.sort_by(|a, b| b.fitness.total_cmp(&a.fitness)),
+            MigrantPolicy::Random => candidates.shuffle(rng),
+        }
+        let picked: HashSet<u64> = candidates.iter().take(wanted).map(|individual| individual.id).collect();
+        // Keep population order so the result doesn't depend on the hash set
+        individuals.iter().map(|individual| individual.id).filter(|id| picked.contains(id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::seeded_rng;
+
+    // `count` islands of `size` cubes, every individual with its own fitness
+    fn archipelago(count: usize, size: usize, topology: MigrationTopology, policy: MigrantPolicy) -> Archipelago {
+        let mut rng = seeded_rng(6);
+        let base = Population::random_cubes(size, &mut rng);
+        let migration = Migration {
+            topology,
+            interval: 1,
+            migrants: 2,
+            policy,
+        };
+        let mut archipelago = Archipelago::new(&base, count, Vec::new(), migration, &mut rng);
+        for island in archipelago.islands.iter_mut() {
+            for individual in island.individuals.iter_mut() {
+                individual.fitness = (individual.id as f32 * 0.618_034).fract();
+            }
+        }
+        archipelago
+    }
+
+    // How many migrants went from one island to another
+    fn routes(migrants: &[Migrant]) -> HashMap<(usize, usize), usize> {
+        let mut routes = HashMap::new();
+        for migrant in migrants {
+            *routes.entry((migrant.from, migrant.to)).or_default() += 1;
+        }
+        routes
+    }
+
+    #[test]
+    fn ring_and_fully_connected_routes() {
+        let mut ring = archipelago(3, 10, MigrationTopology::Ring, MigrantPolicy::Best);
+        ring.migrate(&mut seeded_rng(1));
+        assert_eq!(routes(&ring.migrants), HashMap::from([((0, 1), 2), ((1, 2), 2), ((2, 0), 2)]));
+
+        let mut full = archipelago(3, 10, MigrationTopology::FullyConnected, MigrantPolicy::Best);
+        full.migrate(&mut seeded_rng(1));
+        let expected = [(0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1)].map(|route| (route, 2));
+        assert_eq!(routes(&full.migrants), HashMap::from(expected));
+        assert!(full.islands.iter().all(|island| island.individuals.len() == 10));
+    }
+
+    #[test]
+    fn random_topology_picks_one_other_island() {
+        let mut seen = HashSet::new();
+        let mut rng = seeded_rng(2);
+        for _ in 0..50 {
+            let mut archipelago = archipelago(4, 10, MigrationTopology::Random, MigrantPolicy::Best);
+            archipelago.migrate(&mut rng);
+            for from in 0..4 {
+                let destinations: HashSet<usize> = archipelago
+                    .migrants
+                    .iter()
+                    .filter(|migrant| migrant.from == from)
+                    .map(|migrant| migrant.to)
+                    .collect();
+                assert_eq!(destinations.len(), 1);
+                assert!(!destinations.contains(&from));
+                seen.extend(destinations.into_iter().map(|to| (from, to)));
+            }
+        }
+        // Every route shows up sooner or later
+        assert_eq!(seen.len(), 4 * 3);
+    }
+
+    #[test]
+    fn best_policy_sends_the_fittest() {
+        let archipelago = archipelago(2, 10, MigrationTopology::Ring, MigrantPolicy::Best);
+        let mut ranked = archipelago.islands[0].individuals.clone();
+        ranked.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        let best: HashSet<u64> = ranked.iter().take(3).map(|individual| individual.id).collect();
+        let picked = archipelago.pick_migrants(0, 3, &mut seeded_rng(1));
+        assert_eq!(picked.iter().copied().collect::<HashSet<u64>>(), best);
+        // One always stays behind
+        assert_eq!(archipelago.pick_migrants(0, 100, &mut seeded_rng(1)).len(), 9);
+    }
+
+    #[test]
+    fn random_policy_sends_anyone() {
+        let archipelago = archipelago(2, 10, MigrationTopology::Ring, MigrantPolicy::Random);
+        let mut rng = seeded_rng(3);
+        let mut sent = HashSet::new();
+        for _ in 0..50 {
+            let picked = archipelago.pick_migrants(0, 3, &mut rng);
+            assert_eq!(picked.iter().collect::<HashSet<_>>().len(), 3);
+            sent.extend(picked);
+        }
+        assert_eq!(sent.len(), 10);
+    }
+
+    // Arrivals and the ones who stayed compete for the places, the worst die
+    #[test]
+    fn migrants_replace_the_worst() {
+        let mut crowded = 0;
+        for seed in 0..20 {
+            let mut archipelago = archipelago(3, 10, MigrationTopology::Random, MigrantPolicy::Random);
+            let before = archipelago.islands.clone();
+            archipelago.migrate(&mut seeded_rng(seed));
+
+            for (index, island) in archipelago.islands.iter().enumerate() {
+                let moved = |migrant: &&Migrant| migrant.from == index || migrant.to == index;
+                let moved: HashSet<u64> = archipelago.migrants.iter().filter(moved).map(|migrant| migrant.id).collect();
+                let stayed = before[index].individuals.iter().filter(|individual| !moved.contains(&individual.id));
+                let arrived = before
+                    .iter()
+                    .enumerate()
+                    .filter(|&(from, _)| from != index)
+                    .flat_map(|(_, island)| island.individuals.iter())
+                    .filter(|individual| moved.contains(&individual.id));
+                let mut candidates: Vec<Individual> = stayed.chain(arrived).cloned().collect();
+                candidates.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+                if candidates.len() > island.size {
+                    crowded += 1;
+                    assert_eq!(island.deaths, candidates.len() - island.size);
+                }
+                candidates.truncate(island.size);
+                let kept: HashSet<u64> = island.individuals.iter().map(|individual| individual.id).collect();
+                assert_eq!(kept, candidates.iter().map(|individual| individual.id).collect());
+            }
+        }
+        assert!(crowded > 0);
+    }
+}
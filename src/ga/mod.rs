@@ -0,0 +1,44 @@
+// Headless genetic algorithm core.
+// Nothing in this module touches Bevy, so generations can be run from tests
+// and batch jobs without an App. The InitPlugin only mirrors this state.
+mod crossover;
+mod fitness;
+mod fitness_cache;
+mod genome;
+mod hall_of_fame;
+mod island;
+mod mutation;
+mod niching;
+mod population;
+mod replacement;
+mod representations;
+mod selection;
+mod spatial;
+mod stats;
+mod termination;
+
+pub use crossover::*;
+pub use fitness::*;
+pub use fitness_cache::*;
+pub use genome::*;
+pub use hall_of_fame::*;
+pub use island::*;
+pub use mutation::*;
+pub use niching::*;
+pub use population::*;
+pub use replacement::*;
+pub use representations::*;
+pub use selection::*;
+pub use spatial::*;
+pub use stats::*;
+pub use termination::*;
+
+use rand::SeedableRng;
+
+// The RNG every reproducible run goes through: same seed and same settings
+// give the same populations generation by generation.
+pub type GaRng = rand_chacha::ChaCha8Rng;
+
+pub fn seeded_rng(seed: u64) -> GaRng {
+    GaRng::seed_from_u64(seed)
+}
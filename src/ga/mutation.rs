@@ -0,0 +1,267 @@
+// Mutation operators. None of them knows about the fitness target except
+// YellowBias, which has to be added on purpose.
+// The rate is a per gene chance, except for inversion and scramble where it
+// is the chance that the genome gets one segment reordered.
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum MutationOperator {
+    // Replace the allele with a random valid one
+    Reset,
+    // Add uniform noise in [-step, step]. Like Gaussian and Polynomial it
+    // needs real valued genes, on cubes it only moves the position.
+    Creep { step: f32 },
+    Gaussian { sigma: f32 },
+    // Deb's polynomial mutation with distribution index eta
+    Polynomial { eta: f32 },
+    Swap,
+    Inversion,
+    Scramble,
+    // Flips bits, on cubes a flipped color gene takes another color
+    BitFlip,
+    // Biased: turns color genes yellow, the chance grows by `growth` every
+    // generation. Only the cube genome reacts to it.
+    YellowBias { growth: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Mutation {
+    #[serde(flatten)]
+    pub operator: MutationOperator,
+    pub rate: f32,
+}
+
+impl Mutation {
+    pub fn new(operator: MutationOperator, rate: f32) -> Self {
+        Mutation { operator, rate }
+    }
+
+    fn happens<R: Rng + ?Sized>(&self, rng: &mut R) -> bool {
+        rng.gen::<f32>() < self.rate
+    }
+
+    // Gene-wise reset for discrete alleles, with the sequence operators
+    // available as well
+    pub fn discrete<T, R: Rng + ?Sized>(&self, genes: &mut [T], rng: &mut R, mut random_allele: impl FnMut(&mut R) -> T) {
+        match self.operator {
+            MutationOperator::Reset => {
+                for gene in genes.iter_mut() {
+                    if self.happens(rng) {
+                        *gene = random_allele(rng);
+                    }
+                }
+            }
+            _ => self.sequence(genes, rng),
+        }
+    }
+
+    // Real valued operators kept inside [min, max]
+    pub fn real<R: Rng + ?Sized>(&self, values: &mut [f32], min: f32, max: f32, rng: &mut R) {
+        let mut apply = |mutate: &mut dyn FnMut(f32, &mut R) -> f32| {
+            for value in values.iter_mut() {
+                if self.happens(rng) {
+                    *value = mutate(*value, rng).clamp(min, max);
+                }
+            }
+        };
+
+        match self.operator {
+            MutationOperator::Reset => apply(&mut |_, rng| rng.gen_range(min..=max)),
+            MutationOperator::Creep { step } => apply(&mut |x, rng| x + rng.gen_range(-step..=step)),
+            MutationOperator::Gaussian { sigma } => apply(&mut |x, rng| x + sigma * gaussian(rng)),
+            MutationOperator::Polynomial { eta } => apply(&mut |x, rng| polynomial(x, min, max, eta, rng)),
+            _ => self.sequence(values, rng),
+        }
+    }
+
+    // Reordering operators, they never change which alleles are present
+    pub fn sequence<T, R: Rng + ?Sized>(&self, genes: &mut [T], rng: &mut R) {
+        let len = genes.len();
+        if len < 2 {
+            return;
+        }
+
+        match self.operator {
+            MutationOperator::Swap => {
+                for i in 0..len {
+                    if self.happens(rng) {
+                        genes.swap(i, rng.gen_range(0..len));
+                    }
+                }
+            }
+            MutationOperator::Inversion | MutationOperator::Scramble if self.happens(rng) => {
+                let (mut start, mut end) = (rng.gen_range(0..len), rng.gen_range(0..len));
+                if start > end {
+                    std::mem::swap(&mut start, &mut end);
+                }
+                let segment = &mut genes[start..=end];
+                if self.operator == MutationOperator::Inversion {
+                    segment.reverse();
+                } else {
+                    segment.shuffle(rng);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn bits<R: Rng + ?Sized>(&self, bits: &mut [bool], rng: &mut R) {
+        match self.operator {
+            MutationOperator::BitFlip => {
+                for bit in bits.iter_mut() {
+                    if self.happens(rng) {
+                        *bit = !*bit;
+                    }
+                }
+            }
+            _ => self.discrete(bits, rng, |rng| rng.gen()),
+        }
+    }
+}
+
+impl MutationOperator {
+    // One of each operator with default parameters, used by the UI
+    pub const ALL: [MutationOperator; 9] = [
+        MutationOperator::Reset,
+        MutationOperator::Creep { step: 0.1 },
+        MutationOperator::Gaussian { sigma: 0.1 },
+        MutationOperator::Polynomial { eta: 20.0 },
+        MutationOperator::Swap,
+        MutationOperator::Inversion,
+        MutationOperator::Scramble,
+        MutationOperator::BitFlip,
+        MutationOperator::YellowBias { growth: 0.0005 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MutationOperator::Reset => "Uniform reset",
+            MutationOperator::Creep { .. } => "Creep",
+            MutationOperator::Gaussian { .. } => "Gaussian",
+            MutationOperator::Polynomial { .. } => "Polynomial",
+            MutationOperator::Swap => "Swap",
+            MutationOperator::Inversion => "Inversion",
+            MutationOperator::Scramble => "Scramble",
+            MutationOperator::BitFlip => "Bit-flip",
+            MutationOperator::YellowBias { .. } => "Yellow bias (biased)",
+        }
+    }
+
+    pub fn same_kind(&self, other: &MutationOperator) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+// Standard normal sample using the Box-Muller transform
+fn gaussian<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+fn polynomial<R: Rng + ?Sized>(x: f32, min: f32, max: f32, eta: f32, rng: &mut R) -> f32 {
+    let range = max - min;
+    if range <= 0.0 {
+        return x;
+    }
+    let power = 1.0 / (eta + 1.0);
+    let u: f32 = rng.gen();
+
+    let delta = if u < 0.5 {
+        let xy = 1.0 - (x - min) / range;
+        let value = 2.0 * u + (1.0 - 2.0 * u) * xy.powf(eta + 1.0);
+        value.powf(power) - 1.0
+    } else {
+        let xy = 1.0 - (max - x) / range;
+        let value = 2.0 * (1.0 - u) + 2.0 * (u - 0.5) * xy.powf(eta + 1.0);
+        1.0 - value.powf(power)
+    };
+    x + delta * range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::{seeded_rng, CubeGenome, CubeParams, Genome, COLOR_GROUPS, YELLOW};
+
+    fn real(operator: MutationOperator, rate: f32, values: &[f32]) -> Vec<f32> {
+        let mut values = values.to_vec();
+        Mutation::new(operator, rate).real(&mut values, -1.0, 1.0, &mut seeded_rng(6));
+        values
+    }
+
+    #[test]
+    fn rate_zero_changes_nothing() {
+        let values = [0.1, -0.4, 0.9];
+        for operator in MutationOperator::ALL {
+            assert_eq!(real(operator, 0.0, &values), values, "{operator:?}");
+        }
+    }
+
+    #[test]
+    fn real_operators_stay_within_bounds() {
+        let values = [0.99, -0.99, 0.0, 0.5];
+        let creep = real(MutationOperator::Creep { step: 0.1 }, 1.0, &values);
+        assert!(creep.iter().zip(values).all(|(after, before)| (after - before).abs() <= 0.1 + 1e-6));
+        for operator in [
+            MutationOperator::Reset,
+            MutationOperator::Creep { step: 5.0 },
+            MutationOperator::Gaussian { sigma: 5.0 },
+            MutationOperator::Polynomial { eta: 0.0 },
+            MutationOperator::Polynomial { eta: 20.0 },
+        ] {
+            let mutated = real(operator, 1.0, &values);
+            assert!(mutated.iter().all(|value| (-1.0..=1.0).contains(value)), "{operator:?}: {mutated:?}");
+            assert_ne!(mutated, values, "{operator:?}");
+        }
+    }
+
+    // Reordering never adds or drops an allele
+    #[test]
+    fn sequence_operators_keep_the_alleles() {
+        for operator in [MutationOperator::Swap, MutationOperator::Inversion, MutationOperator::Scramble] {
+            let mut genes: Vec<usize> = (0..20).collect();
+            let mut rng = seeded_rng(7);
+            for _ in 0..10 {
+                Mutation::new(operator, 1.0).sequence(&mut genes, &mut rng);
+            }
+            assert_ne!(genes, (0..20).collect::<Vec<_>>(), "{operator:?}");
+            genes.sort();
+            assert_eq!(genes, (0..20).collect::<Vec<_>>(), "{operator:?}");
+        }
+    }
+
+    #[test]
+    fn bit_flip_flips_every_bit_at_rate_one() {
+        let mut bits = vec![true, false, false, true];
+        Mutation::new(MutationOperator::BitFlip, 1.0).bits(&mut bits, &mut seeded_rng(8));
+        assert_eq!(bits, vec![false, true, true, false]);
+    }
+
+    // On cubes BitFlip and Reset change colors, Creep only moves the cube
+    #[test]
+    fn cube_operators_hit_the_right_genes() {
+        let params = CubeParams::default();
+        let mut rng = seeded_rng(9);
+        let genome = CubeGenome::random(&params, &mut rng);
+
+        let mut flipped = genome.clone();
+        flipped.mutate(&Mutation::new(MutationOperator::BitFlip, 1.0), &params, 0, &mut rng);
+        assert!(flipped.genes.iter().zip(genome.genes).all(|(&after, before)| after != before && after < COLOR_GROUPS));
+        assert_ne!(flipped.color_group, genome.color_group);
+        assert_eq!(flipped.position, genome.position);
+
+        let mut crept = genome.clone();
+        crept.mutate(&Mutation::new(MutationOperator::Creep { step: 0.5 }, 1.0), &params, 0, &mut rng);
+        assert_eq!((crept.color_group, crept.genes), (genome.color_group, genome.genes));
+        assert_ne!(crept.position, genome.position);
+
+        // The yellow bias chance grows with the generation until it's certain
+        let mut biased = genome.clone();
+        biased.mutate(&Mutation::new(MutationOperator::YellowBias { growth: 0.01 }, 0.0), &params, 100, &mut rng);
+        assert_eq!(biased.genes, [YELLOW; 5]);
+    }
+}
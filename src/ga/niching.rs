@@ -0,0 +1,261 @@
+// Niching: ways to keep several niches (colors) alive instead of letting the
+// whole population converge on the fittest one.
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::fitness_cache::genome_hash;
+use super::genome::Genome;
+use super::population::Individual;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Niching {
+    #[default]
+    None,
+    // Parents are picked on their fitness divided by how crowded their niche
+    // is, every neighbor closer than `radius` counting 1 - (d / radius)^alpha
+    Sharing { radius: f32, alpha: f32 },
+    // Only the `capacity` best of every niche of `radius` keep their fitness
+    // for parent selection, the others get 0
+    Clearing { radius: f32, capacity: usize },
+    // Random pairs, each child replaces the parent closest to it if it is at
+    // least as fit
+    Crowding,
+    // NEAT-style species: an individual joins the first species whose
+    // representative is closer than `threshold`. Every member's fitness is
+    // shared by the species size, each species breeds in proportion to the
+    // sum of its shared fitness and parents are picked inside it.
+    Speciation { threshold: f32 },
+}
+
+impl Niching {
+    pub const ALL: [Niching; 5] = [
+        Niching::None,
+        Niching::Sharing { radius: 2.0, alpha: 1.0 },
+        Niching::Clearing { radius: 1.5, capacity: 1 },
+        Niching::Crowding,
+        Niching::Speciation { threshold: 2.5 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Niching::None => "None",
+            Niching::Sharing { .. } => "Fitness sharing",
+            Niching::Clearing { .. } => "Clearing",
+            Niching::Crowding => "Deterministic crowding",
+            Niching::Speciation { .. } => "Speciation",
+        }
+    }
+
+    pub fn same_kind(&self, other: &Niching) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+// Genomes at distance 0 from each other are compared once, so the pairwise
+// work grows with the number of distinct genomes rather than the population
+// size. The hash only narrows down the candidates, distance decides.
+fn distinct<G: Genome>(individuals: &[Individual<G>]) -> (Vec<&G>, Vec<usize>) {
+    let mut genomes: Vec<&G> = Vec::new();
+    let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+    let groups = individuals
+        .iter()
+        .map(|individual| {
+            let bucket = buckets.entry(genome_hash(&individual.genome)).or_default();
+            match bucket.iter().find(|&&slot| genomes[slot].distance(&individual.genome) == 0.0) {
+                Some(&slot) => slot,
+                None => {
+                    genomes.push(&individual.genome);
+                    bucket.push(genomes.len() - 1);
+                    genomes.len() - 1
+                }
+            }
+        })
+        .collect();
+    (genomes, groups)
+}
+
+// Fitness of every individual divided by its niche count
+pub fn shared_fitness<G: Genome>(individuals: &[Individual<G>], radius: f32, alpha: f32) -> Vec<f32> {
+    let (genomes, groups) = distinct(individuals);
+    let mut counts = vec![0.0f32; genomes.len()];
+    for &group in groups.iter() {
+        counts[group] += 1.0;
+    }
+    let niches: Vec<f32> = genomes
+        .par_iter()
+        .map(|genome| {
+            genomes
+                .iter()
+                .zip(counts.iter())
+                .map(|(other, count)| {
+                    let distance = genome.distance(other);
+                    if distance < radius {
+                        count * (1.0 - (distance / radius).powf(alpha))
+                    } else {
+                        0.0
+                    }
+                })
+                .sum()
+        })
+        .collect();
+    // An individual always counts itself, so the niche count is at least 1
+    individuals.iter().zip(groups).map(|(individual, group)| individual.fitness / niches[group]).collect()
+}
+
+// Fitness after clearing: the best individual not yet in a niche founds one,
+// and the first `capacity` members of every niche keep their fitness
+pub fn cleared_fitness<G: Genome>(individuals: &[Individual<G>], radius: f32, capacity: usize) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..individuals.len()).collect();
+    order.sort_by(|&a, &b| individuals[b].fitness.total_cmp(&individuals[a].fitness));
+
+    let mut fitness = vec![0.0; individuals.len()];
+    // founder and members so far of every niche
+    let mut niches: Vec<(usize, usize)> = Vec::new();
+    for index in order {
+        let genome = &individuals[index].genome;
+        match niches.iter_mut().find(|(founder, _)| individuals[*founder].genome.distance(genome) < radius) {
+            Some((_, members)) => {
+                if *members < capacity {
+                    fitness[index] = individuals[index].fitness;
+                }
+                *members += 1;
+            }
+            None => {
+                fitness[index] = individuals[index].fitness;
+                niches.push((index, 1));
+            }
+        }
+    }
+    fitness
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Species<G> {
+    pub id: u32,
+    // newcomers are compared to it, the fittest member after every speciate
+    pub representative: G,
+    // ids of the individuals in the species
+    pub members: Vec<u64>,
+    // pairs bred by the species during the last generation
+    pub offspring: usize,
+}
+
+// Sorts `individuals` into `species`, creating new ones as needed and
+// dropping the ones left empty. New species get `next_id`, which then moves
+// on by `stride`.
+pub fn speciate<G: Genome>(
+    species: &mut Vec<Species<G>>,
+    individuals: &[Individual<G>],
+    threshold: f32,
+    next_id: &mut u32,
+    stride: u32,
+) {
+    for existing in species.iter_mut() {
+        existing.members.clear();
+    }
+    // fittest member of every species, to become its representative
+    let mut champions: Vec<Option<usize>> = vec![None; species.len()];
+    for (index, individual) in individuals.iter().enumerate() {
+        let found = species.iter().position(|existing| existing.representative.distance(&individual.genome) < threshold);
+        let slot = match found {
+            Some(slot) => slot,
+            None => {
+                species.push(Species {
+                    id: *next_id,
+                    representative: individual.genome.clone(),
+                    members: Vec::new(),
+                    offspring: 0,
+                });
+                champions.push(None);
+                *next_id += stride;
+                species.len() - 1
+            }
+        };
+        species[slot].members.push(individual.id);
+        if champions[slot].is_none_or(|champion| individual.fitness > individuals[champion].fitness) {
+            champions[slot] = Some(index);
+        }
+    }
+
+    for (existing, champion) in species.iter_mut().zip(champions) {
+        if let Some(champion) = champion {
+            existing.representative = individuals[champion].genome.clone();
+        }
+    }
+    species.retain(|existing| !existing.members.is_empty());
+}
+
+// Splits `total` pairs between species in proportion to their summed shared
+// fitness: every member's fitness divided by the species size, which is the
+// species' mean fitness. A big species of average cubes doesn't outbreed a
+// small fit one. Largest remainders get the pairs left over after rounding
+// down.
+pub fn allocate_offspring<G>(species: &[Species<G>], fitness: &HashMap<u64, f32>, total: usize) -> Vec<usize> {
+    let shares: Vec<f64> = species
+        .iter()
+        .map(|existing| {
+            let sum: f64 = existing.members.iter().map(|id| fitness.get(id).copied().unwrap_or(0.0).max(0.0) as f64).sum();
+            sum / existing.members.len().max(1) as f64
+        })
+        .collect();
+    let sum: f64 = shares.iter().sum();
+    // Nobody has any fitness, every species breeds by its size
+    let shares: Vec<f64> = if sum > 0.0 {
+        shares
+    } else {
+        species.iter().map(|existing| existing.members.len() as f64).collect()
+    };
+    let sum: f64 = shares.iter().sum::<f64>().max(f64::EPSILON);
+
+    let exact: Vec<f64> = shares.iter().map(|share| share / sum * total as f64).collect();
+    let mut allocated: Vec<usize> = exact.iter().map(|value| value.floor() as usize).collect();
+    let mut order: Vec<usize> = (0..species.len()).collect();
+    order.sort_by(|&a, &b| (exact[b] - exact[b].floor()).total_cmp(&(exact[a] - exact[a].floor())));
+    let missing = total.saturating_sub(allocated.iter().sum());
+    for &slot in order.iter().take(missing) {
+        allocated[slot] += 1;
+    }
+    allocated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::{seeded_rng, Archipelago, Migration, Population};
+
+    fn species(id: u32, members: std::ops::Range<u64>) -> Species<()> {
+        Species {
+            id,
+            representative: (),
+            members: members.collect(),
+            offspring: 0,
+        }
+    }
+
+    // Eight mediocre members share their fitness eight ways, the two fit
+    // ones only two ways
+    #[test]
+    fn offspring_follow_shared_fitness() {
+        let table = [species(0, 0..8), species(1, 8..10)];
+        let fitness: HashMap<u64, f32> = (0..10).map(|id| (id, if id < 8 { 0.5 } else { 0.9 })).collect();
+        assert_eq!(allocate_offspring(&table, &fitness, 14), vec![5, 9]);
+    }
+
+    // Every island numbers its species apart from the others
+    #[test]
+    fn species_ids_unique_across_islands() {
+        let mut rng = seeded_rng(3);
+        let mut population = Population::random_cubes(40, &mut rng);
+        population.niching = Niching::Speciation { threshold: 2.0 };
+        let mut islands = Archipelago::new(&population, 3, Vec::new(), Migration::default(), &mut rng);
+        for _ in 0..5 {
+            islands.evolve(&mut population, &mut rng);
+        }
+        let ids: Vec<u32> = population.species.iter().map(|species| species.id).collect();
+        let unique: std::collections::HashSet<u32> = ids.iter().copied().collect();
+        assert!(ids.len() > 3);
+        assert_eq!(unique.len(), ids.len());
+    }
+}
@@ -0,0 +1,719 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use super::fitness::{ColorFitness, FitnessFunction};
+use super::fitness_cache::FitnessCache;
+use super::crossover::CrossoverOperator;
+use super::genome::{CubeGenome, CubeParams, Genome};
+use super::mutation::{Mutation, MutationOperator};
+use super::niching::{allocate_offspring, cleared_fitness, shared_fitness, speciate, Niching, Species};
+use super::replacement::ReplacementModel;
+use super::selection::SelectionStrategy;
+use super::seeded_rng;
+use super::spatial::{lattice_point, on_lattice, Neighborhood, SpatialHash};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Individual<G = CubeGenome> {
+    pub id: u64,
+    pub genome: G,
+    pub fitness: f32,
+}
+
+#[derive(Clone)]
+pub struct Population<G: Genome = CubeGenome> {
+    // the breeding population
+    pub individuals: Vec<Individual<G>>,
+    // children bred by the last generation, survivors are also in individuals
+    pub offspring: Vec<Individual<G>>,
+    // mu, the population size every generation is brought back to
+    pub size: usize,
+    pub replacement: ReplacementModel,
+    pub generation: u32,
+    pub params: G::Params,
+    pub selection: SelectionStrategy,
+    pub crossover: CrossoverOperator,
+    // chance that a selected pair is recombined instead of copied
+    pub crossover_rate: f32,
+    // keep both children of every pair instead of only the first one
+    pub two_offspring: bool,
+    // applied in order to every child
+    pub mutations: Vec<Mutation>,
+    // best individuals carried over unchanged into the next generation
+    pub elite_count: usize,
+    // who can mate with whom, anything but Panmictic makes a cellular GA
+    pub neighborhood: Neighborhood,
+    // how diversity is protected, see `Niching`
+    pub niching: Niching,
+    // species of the last generation, only kept with Niching::Speciation
+    pub species: Vec<Species<G>>,
+    // children created and individuals eliminated during the last generation
+    pub births: usize,
+    pub deaths: usize,
+    // scores of genomes already seen, emptied when the fitness function changes
+    pub cache: FitnessCache,
+    fitness: Arc<dyn FitnessFunction<G>>,
+    // children and deaths from mate() since the last census
+    brood: Vec<Individual<G>>,
+    brood_deaths: usize,
+    next_id: u64,
+    next_species: u32,
+    // gap between two ids and two species ids, islands interleave theirs so
+    // they never clash
+    id_stride: u64,
+}
+
+// Everything that changes while a population evolves, its settings and
+// fitness function excluded. Saved in checkpoints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PopulationState<G> {
+    pub individuals: Vec<Individual<G>>,
+    pub offspring: Vec<Individual<G>>,
+    pub generation: u32,
+    pub births: usize,
+    pub deaths: usize,
+    pub next_id: u64,
+    pub species: Vec<Species<G>>,
+    pub next_species: u32,
+}
+
+impl Population<CubeGenome> {
+    pub fn random_cubes<R: Rng + ?Sized>(size: usize, rng: &mut R) -> Self {
+        Population::random(size, CubeParams::default(), ColorFitness::default(), rng)
+    }
+}
+
+impl<G: Genome> Population<G> {
+    pub fn new(params: G::Params, fitness: impl FitnessFunction<G> + 'static) -> Self {
+        Population {
+            individuals: Vec::new(),
+            offspring: Vec::new(),
+            size: 0,
+            replacement: ReplacementModel::default(),
+            generation: 0,
+            params,
+            selection: SelectionStrategy::default(),
+            crossover: CrossoverOperator::default(),
+            crossover_rate: 1.0,
+            two_offspring: false,
+            mutations: vec![Mutation::new(MutationOperator::Reset, 0.01)],
+            elite_count: 0,
+            neighborhood: Neighborhood::default(),
+            niching: Niching::default(),
+            species: Vec::new(),
+            births: 0,
+            deaths: 0,
+            cache: FitnessCache::default(),
+            fitness: Arc::new(fitness),
+            brood: Vec::new(),
+            brood_deaths: 0,
+            next_id: 0,
+            next_species: 0,
+            id_stride: 1,
+        }
+    }
+
+    pub fn random<R: Rng + ?Sized>(
+        size: usize,
+        params: G::Params,
+        fitness: impl FitnessFunction<G> + 'static,
+        rng: &mut R,
+    ) -> Self {
+        let mut population = Population::new(params, fitness);
+        population.size = size;
+        population.fill_random(rng);
+        population
+    }
+
+    // Replaces everyone with `size` random individuals
+    pub fn fill_random<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let genomes = (0..self.size).map(|_| G::random(&self.params, rng)).collect();
+        self.individuals = self.newborns(genomes);
+        self.offspring.clear();
+        self.species.clear();
+        self.speciate();
+    }
+
+    // New ids will be first, first + stride, first + 2 * stride... and so
+    // will new species ids
+    pub fn number_ids(&mut self, first: u64, stride: u64) {
+        self.next_id = first;
+        self.next_species = first as u32;
+        self.id_stride = stride;
+    }
+
+    // Takes the settings and fitness function of `other`, individuals and
+    // ids excepted. Re-scores everyone if the fitness function changed.
+    pub fn configure_from(&mut self, other: &Population<G>) {
+        self.size = other.size;
+        self.replacement = other.replacement;
+        self.params = other.params.clone();
+        self.selection = other.selection;
+        self.crossover = other.crossover;
+        self.crossover_rate = other.crossover_rate;
+        self.two_offspring = other.two_offspring;
+        self.mutations = other.mutations.clone();
+        self.elite_count = other.elite_count;
+        self.neighborhood = other.neighborhood;
+        self.niching = other.niching;
+        self.cache.capacity = other.cache.capacity;
+        if !Arc::ptr_eq(&self.fitness, &other.fitness) {
+            self.fitness = other.fitness.clone();
+            self.rescore();
+        }
+    }
+
+    // New individuals with fresh ids, scored in parallel
+    fn newborns(&mut self, genomes: Vec<G>) -> Vec<Individual<G>> {
+        let scores = self.cache.evaluate(self.fitness.as_ref(), &genomes.iter().collect::<Vec<_>>());
+        genomes
+            .into_iter()
+            .zip(scores)
+            .map(|(genome, fitness)| {
+                let id = self.next_id;
+                self.next_id += self.id_stride;
+                Individual { id, genome, fitness }
+            })
+            .collect()
+    }
+
+    pub fn step<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        if self.individuals.is_empty() {
+            return;
+        }
+        if self.neighborhood.is_spatial() {
+            self.step_cellular(rng);
+            return;
+        }
+        if self.niching == Niching::Crowding {
+            self.step_crowding(rng);
+            return;
+        }
+
+        // 1. Breed the children the replacement model asks for
+        let elites = self.elites();
+        let children = self.breed(self.replacement.brood_size(self.size, elites.len()), rng);
+        self.births = children.len();
+
+        // 2. Decide who makes it into the next generation
+        let mut next = match self.replacement {
+            ReplacementModel::Generational => elites,
+            ReplacementModel::SteadyState { .. } => {
+                let mut survivors = self.individuals.clone();
+                survivors.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+                survivors.truncate(self.size.saturating_sub(children.len()));
+                survivors
+            }
+            ReplacementModel::MuPlusLambda { .. } => self.individuals.clone(),
+            ReplacementModel::MuCommaLambda { .. } => elites,
+        };
+        next.extend(children.iter().cloned());
+        next.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        next.truncate(self.size);
+
+        let survivors: HashSet<u64> = next.iter().map(|individual| individual.id).collect();
+        self.deaths = self.individuals.iter().filter(|individual| !survivors.contains(&individual.id)).count();
+        self.individuals = next;
+        self.offspring = children;
+
+        // 3. Increment generation counter. Everyone was scored when born, so
+        // there's nothing to re-evaluate.
+        self.generation += 1;
+        self.speciate();
+    }
+
+    // Selects, recombines and mutates `count` new individuals. Pairs are bred
+    // in parallel, each with its own RNG seeded from `rng`, so the result only
+    // depends on the seed and not on the thread count.
+    fn breed<R: Rng + ?Sized>(&mut self, count: usize, rng: &mut R) -> Vec<Individual<G>> {
+        // Consecutive picks are mated together
+        let pairs = if self.two_offspring { count.div_ceil(2) } else { count };
+        let parents = self.select_parents(pairs, rng);
+        let seeds: Vec<u64> = (0..pairs).map(|_| rng.gen()).collect();
+
+        let broods: Vec<(G, Option<G>)> = parents
+            .par_chunks(2)
+            .zip(seeds)
+            .map(|(pair, seed)| {
+                let mut rng = seeded_rng(seed);
+                let (parent1, parent2) = (&self.individuals[pair[0]].genome, &self.individuals[pair[1]].genome);
+                let (mut child1, mut child2) = if rng.gen::<f32>() < self.crossover_rate {
+                    parent1.crossover(parent2, &self.crossover, &self.params, &mut rng)
+                } else {
+                    (parent1.clone(), parent2.clone())
+                };
+
+                for mutation in self.mutations.iter() {
+                    child1.mutate(mutation, &self.params, self.generation, &mut rng);
+                }
+                if !self.two_offspring {
+                    return (child1, None);
+                }
+                for mutation in self.mutations.iter() {
+                    child2.mutate(mutation, &self.params, self.generation, &mut rng);
+                }
+                (child1, Some(child2))
+            })
+            .collect();
+
+        let genomes = broods
+            .into_iter()
+            .flat_map(|(child1, child2)| std::iter::once(child1).chain(child2))
+            .take(count)
+            .collect();
+        self.newborns(genomes)
+    }
+
+    // Indices of `pairs` pairs of parents. Niching changes the fitness the
+    // selection strategy sees, and with species both parents of a pair come
+    // from the same species.
+    fn select_parents<R: Rng + ?Sized>(&mut self, pairs: usize, rng: &mut R) -> Vec<usize> {
+        match self.niching {
+            Niching::Sharing { radius, alpha } => {
+                let fitness = shared_fitness(&self.individuals, radius, alpha);
+                self.selection.select_by(&fitness, pairs * 2, rng)
+            }
+            Niching::Clearing { radius, capacity } => {
+                let fitness = cleared_fitness(&self.individuals, radius, capacity);
+                self.selection.select_by(&fitness, pairs * 2, rng)
+            }
+            Niching::Speciation { .. } => {
+                // Culls and migrations may have changed the population since
+                // the last generation
+                self.speciate();
+                let slots: HashMap<u64, usize> =
+                    self.individuals.iter().enumerate().map(|(slot, individual)| (individual.id, slot)).collect();
+                let fitness: HashMap<u64, f32> =
+                    self.individuals.iter().map(|individual| (individual.id, individual.fitness)).collect();
+                let allocated = allocate_offspring(&self.species, &fitness, pairs);
+
+                let mut parents = Vec::with_capacity(pairs * 2);
+                for (species, allocated) in self.species.iter_mut().zip(allocated) {
+                    species.offspring = allocated;
+                    if allocated == 0 {
+                        continue;
+                    }
+                    // Sharing already happened between species, in
+                    // allocate_offspring. Inside one it would scale every
+                    // member alike and change nothing.
+                    let members: Vec<usize> = species.members.iter().map(|id| slots[id]).collect();
+                    let fitness: Vec<f32> = members.iter().map(|&slot| self.individuals[slot].fitness).collect();
+                    let picks = self.selection.select_by(&fitness, allocated * 2, rng);
+                    parents.extend(picks.into_iter().map(|pick| members[pick]));
+                }
+                parents
+            }
+            Niching::None | Niching::Crowding => self.selection.select(&self.individuals, pairs * 2, rng),
+        }
+    }
+
+    // Sorts the individuals into species, a no-op unless speciation is on
+    fn speciate(&mut self) {
+        match self.niching {
+            Niching::Speciation { threshold } => {
+                speciate(&mut self.species, &self.individuals, threshold, &mut self.next_species, self.id_stride as u32)
+            }
+            _ => self.species.clear(),
+        }
+    }
+
+    // Deterministic crowding: the individuals are paired at random, every
+    // pair has two children and each child competes with the parent most
+    // like it, taking its place if it is at least as fit. The replacement
+    // model and elites don't apply, and the population doesn't grow back
+    // after a cull.
+    fn step_crowding<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let mut order: Vec<usize> = (0..self.individuals.len()).collect();
+        order.shuffle(rng);
+        let pairs: Vec<&[usize]> = order.chunks_exact(2).collect();
+        let seeds: Vec<u64> = pairs.iter().map(|_| rng.gen()).collect();
+
+        let broods: Vec<(G, G)> = pairs
+            .par_iter()
+            .zip(seeds)
+            .map(|(pair, seed)| {
+                let mut rng = seeded_rng(seed);
+                let (parent1, parent2) = (&self.individuals[pair[0]].genome, &self.individuals[pair[1]].genome);
+                let (mut child1, mut child2) = if rng.gen::<f32>() < self.crossover_rate {
+                    parent1.crossover(parent2, &self.crossover, &self.params, &mut rng)
+                } else {
+                    (parent1.clone(), parent2.clone())
+                };
+                for mutation in self.mutations.iter() {
+                    child1.mutate(mutation, &self.params, self.generation, &mut rng);
+                }
+                for mutation in self.mutations.iter() {
+                    child2.mutate(mutation, &self.params, self.generation, &mut rng);
+                }
+                (child1, child2)
+            })
+            .collect();
+
+        let genomes = broods.into_iter().flat_map(|(child1, child2)| [child1, child2]).collect();
+        let mut children = self.newborns(genomes).into_iter();
+        self.offspring.clear();
+        for pair in pairs {
+            let (Some(child1), Some(child2)) = (children.next(), children.next()) else {
+                break;
+            };
+            let (slot1, slot2) = (pair[0], pair[1]);
+            let distance = |slot: usize, child: &Individual<G>| self.individuals[slot].genome.distance(&child.genome);
+            let matches = if distance(slot1, &child1) + distance(slot2, &child2)
+                <= distance(slot1, &child2) + distance(slot2, &child1)
+            {
+                [(slot1, child1), (slot2, child2)]
+            } else {
+                [(slot1, child2), (slot2, child1)]
+            };
+            for (slot, child) in matches {
+                if child.fitness >= self.individuals[slot].fitness {
+                    self.individuals[slot] = child.clone();
+                    self.offspring.push(child);
+                }
+            }
+        }
+        // Children that lost to their parent never count as born
+        self.births = self.offspring.len();
+        self.deaths = self.offspring.len();
+
+        self.generation += 1;
+        self.speciate();
+    }
+
+    // Cellular generation: every individual mates with a neighbor picked by
+    // the selection strategy, and the first child takes its place if it is
+    // at least as fit. Children keep their parent's position so demes stay
+    // where they are. The replacement model and elites don't apply, and the
+    // population doesn't grow back after a cull.
+    fn step_cellular<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let count = self.individuals.len();
+        let (reach, cube) = match self.neighborhood {
+            Neighborhood::Panmictic => return,
+            Neighborhood::Radius { radius } => (radius, false),
+            Neighborhood::Lattice { spacing, range } => {
+                // Individuals are put on the lattice by index the first time
+                let positions: Vec<[f32; 3]> = self.individuals.iter().map(|individual| Self::place(individual)).collect();
+                if positions.iter().any(|&position| !on_lattice(position, count, spacing)) {
+                    for (index, individual) in self.individuals.iter_mut().enumerate() {
+                        individual.genome.set_position(lattice_point(index, count, spacing));
+                    }
+                }
+                (spacing * range as f32, true)
+            }
+        };
+
+        let positions: Vec<[f32; 3]> = self.individuals.iter().map(|individual| Self::place(individual)).collect();
+        let hash = SpatialHash::new(reach.max(1e-3), &positions);
+        let seeds: Vec<u64> = (0..count).map(|_| rng.gen()).collect();
+
+        let genomes: Vec<G> = (0..count)
+            .into_par_iter()
+            .zip(seeds)
+            .map(|(index, seed)| {
+                let mut rng = seeded_rng(seed);
+                let parent = &self.individuals[index].genome;
+                let neighbors: Vec<Individual<G>> =
+                    hash.neighbors(index, reach, cube).into_iter().map(|other| self.individuals[other].clone()).collect();
+                // A lone individual only gets mutated
+                let mut child = match self.selection.select(&neighbors, 1, &mut rng).first() {
+                    Some(&mate) if rng.gen::<f32>() < self.crossover_rate => {
+                        parent.crossover(&neighbors[mate].genome, &self.crossover, &self.params, &mut rng).0
+                    }
+                    _ => parent.clone(),
+                };
+                for mutation in self.mutations.iter() {
+                    child.mutate(mutation, &self.params, self.generation, &mut rng);
+                }
+                if let Some(position) = parent.position() {
+                    child.set_position(position);
+                }
+                child
+            })
+            .collect();
+
+        let children = self.newborns(genomes);
+        self.offspring.clear();
+        for (slot, child) in self.individuals.iter_mut().zip(children) {
+            if child.fitness >= slot.fitness {
+                *slot = child.clone();
+                self.offspring.push(child);
+            }
+        }
+        // Children that lost to their parent never count as born
+        self.births = self.offspring.len();
+        self.deaths = self.offspring.len();
+
+        self.generation += 1;
+        self.speciate();
+    }
+
+    fn place(individual: &Individual<G>) -> [f32; 3] {
+        individual.genome.position().unwrap_or_default()
+    }
+
+    // Continuous-time breeding: one child of two given parents, born at
+    // `position`. A full population only takes the child in place of its
+    // least fit individual, and only if the child is fitter. Returns the
+    // child when it made it in.
+    pub fn mate<R: Rng + ?Sized>(
+        &mut self,
+        parent1: u64,
+        parent2: u64,
+        position: [f32; 3],
+        rng: &mut R,
+    ) -> Option<Individual<G>> {
+        let find = |id: u64| self.individuals.iter().find(|individual| individual.id == id);
+        let (parent1, parent2) = (&find(parent1)?.genome, &find(parent2)?.genome);
+        let mut child = if rng.gen::<f32>() < self.crossover_rate {
+            parent1.crossover(parent2, &self.crossover, &self.params, rng).0
+        } else {
+            parent1.clone()
+        };
+        for mutation in self.mutations.iter() {
+            child.mutate(mutation, &self.params, self.generation, rng);
+        }
+        child.set_position(position);
+        let child = self.newborns(vec![child]).pop()?;
+
+        if self.individuals.len() >= self.size {
+            let (worst, _) = self
+                .individuals
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.fitness.total_cmp(&b.fitness))?;
+            if child.fitness <= self.individuals[worst].fitness {
+                return None;
+            }
+            self.individuals.swap_remove(worst);
+            self.brood_deaths += 1;
+        }
+        self.individuals.push(child.clone());
+        self.brood.push(child.clone());
+        Some(child)
+    }
+
+    // Ends a generation of continuous-time breeding: births, deaths and
+    // offspring become the ones of mate() since the last census
+    pub fn census(&mut self) {
+        self.offspring = std::mem::take(&mut self.brood);
+        self.births = self.offspring.len();
+        self.deaths = std::mem::take(&mut self.brood_deaths);
+        self.generation += 1;
+        self.speciate();
+    }
+
+    // The elite_count fittest individuals, best first, ids kept
+    pub fn elites(&self) -> Vec<Individual<G>> {
+        let mut ranked: Vec<&Individual<G>> = self.individuals.iter().collect();
+        ranked.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        ranked.into_iter().take(self.elite_count).cloned().collect()
+    }
+
+    // Parallel, individuals whose genome is in the cache aren't re-scored
+    pub fn evaluate_fitness(&mut self) {
+        Self::score(&mut self.cache, self.fitness.as_ref(), &mut self.individuals);
+    }
+
+    fn score(cache: &mut FitnessCache, fitness: &dyn FitnessFunction<G>, individuals: &mut [Individual<G>]) {
+        let genomes: Vec<&G> = individuals.iter().map(|individual| &individual.genome).collect();
+        let scores = cache.evaluate(fitness, &genomes);
+        for (individual, score) in individuals.iter_mut().zip(scores) {
+            individual.fitness = score;
+        }
+    }
+
+    // Swaps the fitness function and re-scores everyone
+    pub fn set_fitness(&mut self, fitness: impl FitnessFunction<G> + 'static) {
+        self.fitness = Arc::new(fitness);
+        self.rescore();
+    }
+
+    fn rescore(&mut self) {
+        self.cache.clear();
+        self.evaluate_fitness();
+        Self::score(&mut self.cache, self.fitness.as_ref(), &mut self.offspring);
+    }
+
+    pub fn state(&self) -> PopulationState<G> {
+        PopulationState {
+            individuals: self.individuals.clone(),
+            offspring: self.offspring.clone(),
+            generation: self.generation,
+            births: self.births,
+            deaths: self.deaths,
+            next_id: self.next_id,
+            species: self.species.clone(),
+            next_species: self.next_species,
+        }
+    }
+
+    // Picks up where a saved population left off, the fitness is re-scored
+    // with the current function
+    pub fn restore(&mut self, state: PopulationState<G>) {
+        self.individuals = state.individuals;
+        self.offspring = state.offspring;
+        self.generation = state.generation;
+        self.births = state.births;
+        self.deaths = state.deaths;
+        self.next_id = state.next_id;
+        self.species = state.species;
+        self.next_species = state.next_species;
+        self.rescore();
+    }
+
+    // Eliminates every individual scoring below min_fitness
+    pub fn cull(&mut self, min_fitness: f32) {
+        let before = self.individuals.len();
+        self.individuals.retain(|individual| individual.fitness >= min_fitness);
+        self.deaths += before - self.individuals.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::seeded_rng;
+
+    // Thousands of generations through the plain API, no App needed
+    #[test]
+    fn runs_thousands_of_generations() {
+        let mut rng = seeded_rng(1);
+        let mut population = Population::random_cubes(100, &mut rng);
+        population.elite_count = 2;
+        for _ in 0..2000 {
+            population.step(&mut rng);
+        }
+        assert_eq!(population.generation, 2000);
+        assert_eq!(population.individuals.len(), 100);
+        assert!(population.individuals.iter().all(|individual| (0.0..=1.0).contains(&individual.fitness)));
+        let ids: HashSet<u64> = population.individuals.iter().map(|individual| individual.id).collect();
+        assert_eq!(ids.len(), population.individuals.len());
+    }
+
+    // One generation of 100 cubes under `replacement`, before and after
+    fn replace(replacement: ReplacementModel, elite_count: usize) -> (Population, Population) {
+        let mut rng = seeded_rng(8);
+        let mut population = Population::random_cubes(100, &mut rng);
+        population.replacement = replacement;
+        population.elite_count = elite_count;
+        let before = population.clone();
+        population.step(&mut rng);
+        (before, population)
+    }
+
+    fn ids(individuals: &[Individual]) -> HashSet<u64> {
+        individuals.iter().map(|individual| individual.id).collect()
+    }
+
+    // Nobody left out is fitter than someone who made it
+    fn fittest_survive(candidates: &[Individual], survivors: &[Individual]) {
+        let kept = ids(survivors);
+        let worst_kept = survivors.iter().map(|individual| individual.fitness).fold(f32::INFINITY, f32::min);
+        for individual in candidates.iter().filter(|individual| !kept.contains(&individual.id)) {
+            assert!(individual.fitness <= worst_kept);
+        }
+    }
+
+    #[test]
+    fn generational_keeps_only_the_elites() {
+        let (before, after) = replace(ReplacementModel::Generational, 3);
+        let elites = ids(&before.elites());
+        let parents: HashSet<u64> = ids(&after.individuals).intersection(&ids(&before.individuals)).copied().collect();
+        assert_eq!(parents, elites);
+        assert_eq!((after.individuals.len(), after.births, after.deaths), (100, 97, 97));
+    }
+
+    #[test]
+    fn steady_state_replaces_the_worst() {
+        let (before, after) = replace(ReplacementModel::SteadyState { replaced: 10 }, 0);
+        assert_eq!((after.individuals.len(), after.births, after.deaths), (100, 10, 10));
+        assert!(ids(&after.offspring).is_subset(&ids(&after.individuals)));
+        let old = ids(&before.individuals);
+        let parents: Vec<Individual> =
+            after.individuals.iter().filter(|individual| old.contains(&individual.id)).cloned().collect();
+        assert_eq!(parents.len(), 90);
+        fittest_survive(&before.individuals, &parents);
+    }
+
+    #[test]
+    fn mu_plus_lambda_keeps_the_best_of_parents_and_children() {
+        let (before, after) = replace(ReplacementModel::MuPlusLambda { lambda: 150 }, 0);
+        assert_eq!((after.individuals.len(), after.offspring.len()), (100, 150));
+        let candidates: Vec<Individual> = before.individuals.iter().chain(after.offspring.iter()).cloned().collect();
+        fittest_survive(&candidates, &after.individuals);
+    }
+
+    #[test]
+    fn mu_comma_lambda_keeps_the_best_children_and_elites() {
+        let (_, after) = replace(ReplacementModel::MuCommaLambda { lambda: 150 }, 0);
+        assert_eq!((after.individuals.len(), after.deaths), (100, 100));
+        assert!(ids(&after.individuals).is_subset(&ids(&after.offspring)));
+        fittest_survive(&after.offspring, &after.individuals);
+
+        let (before, after) = replace(ReplacementModel::MuCommaLambda { lambda: 150 }, 2);
+        let parents: HashSet<u64> = ids(&after.individuals).intersection(&ids(&before.individuals)).copied().collect();
+        assert_eq!(parents, ids(&before.elites()));
+    }
+
+    fn cellular(seed: u64) -> Population {
+        let mut population = Population::random_cubes(64, &mut seeded_rng(seed));
+        population.neighborhood = Neighborhood::Lattice { spacing: 1.0, range: 1 };
+        population
+    }
+
+    // A child takes the place of its parent only when at least as fit
+    #[test]
+    fn cellular_children_replace_weaker_parents() {
+        let mut rng = seeded_rng(9);
+        let mut population = cellular(9);
+        for _ in 0..3 {
+            let before = population.individuals.clone();
+            population.step(&mut rng);
+            let born = ids(&population.offspring);
+            let mut replaced = 0;
+            for (parent, slot) in before.iter().zip(population.individuals.iter()) {
+                if slot.id == parent.id {
+                    assert_eq!(slot.fitness, parent.fitness);
+                } else {
+                    assert!(slot.fitness >= parent.fitness);
+                    assert!(born.contains(&slot.id));
+                    replaced += 1;
+                }
+            }
+            assert_eq!((population.births, population.deaths, born.len()), (replaced, replaced, replaced));
+            assert!(replaced > 0 && replaced < before.len());
+        }
+
+        // Without crossover or mutation a child is as fit as its parent, and wins
+        let mut population = cellular(9);
+        population.crossover_rate = 0.0;
+        population.mutations.clear();
+        let before = ids(&population.individuals);
+        population.step(&mut rng);
+        assert_eq!(population.births, 64);
+        assert!(ids(&population.individuals).is_disjoint(&before));
+    }
+
+    fn run(seed: u64, generations: u32) -> PopulationState<CubeGenome> {
+        let mut rng = seeded_rng(seed);
+        let mut population = Population::random_cubes(60, &mut rng);
+        population.elite_count = 2;
+        population.two_offspring = true;
+        for _ in 0..generations {
+            population.step(&mut rng);
+            population.cull(0.2);
+        }
+        population.state()
+    }
+
+    // Same seed and settings, same populations generation by generation
+    #[test]
+    fn same_seed_same_populations() {
+        let (first, second) = (run(42, 200), run(42, 200));
+        assert_eq!(format!("{first:?}"), format!("{second:?}"));
+        assert_ne!(format!("{first:?}"), format!("{:?}", run(43, 200)));
+    }
+}
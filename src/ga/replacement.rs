@@ -0,0 +1,64 @@
+// How the offspring of a generation make it into the breeding population.
+// mu is the population size and lambda the number of children bred.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ReplacementModel {
+    // The children replace the whole population, elites excepted
+    #[default]
+    Generational,
+    // `replaced` children take the place of the worst individuals
+    SteadyState { replaced: usize },
+    // (mu+lambda): the best mu of parents and children survive
+    MuPlusLambda { lambda: usize },
+    // (mu,lambda): the best mu of the children (and elites) survive
+    MuCommaLambda { lambda: usize },
+}
+
+impl ReplacementModel {
+    // One of each model with default parameters, used by the UI
+    pub const ALL: [ReplacementModel; 4] = [
+        ReplacementModel::Generational,
+        ReplacementModel::SteadyState { replaced: 10 },
+        ReplacementModel::MuPlusLambda { lambda: 350 },
+        ReplacementModel::MuCommaLambda { lambda: 700 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReplacementModel::Generational => "Generational",
+            ReplacementModel::SteadyState { .. } => "Steady-state",
+            ReplacementModel::MuPlusLambda { .. } => "(mu+lambda)",
+            ReplacementModel::MuCommaLambda { .. } => "(mu,lambda)",
+        }
+    }
+
+    pub fn same_kind(&self, other: &ReplacementModel) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    // Children to breed for a population of `size` keeping `elites` unchanged
+    pub fn brood_size(&self, size: usize, elites: usize) -> usize {
+        match *self {
+            ReplacementModel::Generational => size.saturating_sub(elites),
+            ReplacementModel::SteadyState { replaced } => replaced.min(size.saturating_sub(elites)),
+            ReplacementModel::MuPlusLambda { lambda } | ReplacementModel::MuCommaLambda { lambda } => lambda,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brood_size_leaves_room_for_elites() {
+        assert_eq!(ReplacementModel::Generational.brood_size(100, 2), 98);
+        assert_eq!(ReplacementModel::Generational.brood_size(1, 5), 0);
+        assert_eq!(ReplacementModel::SteadyState { replaced: 10 }.brood_size(100, 2), 10);
+        assert_eq!(ReplacementModel::SteadyState { replaced: 200 }.brood_size(100, 2), 98);
+        assert_eq!(ReplacementModel::MuPlusLambda { lambda: 350 }.brood_size(100, 2), 350);
+        assert_eq!(ReplacementModel::MuCommaLambda { lambda: 700 }.brood_size(100, 2), 700);
+    }
+}
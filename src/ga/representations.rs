@@ -0,0 +1,273 @@
+// Built-in gene representations for the generic pipeline.
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+use super::crossover::CrossoverOperator;
+use super::genome::Genome;
+use super::mutation::Mutation;
+
+fn hamming<T: PartialEq>(a: &[T], b: &[T]) -> f32 {
+    let differing = a.iter().zip(b).filter(|(x, y)| x != y).count();
+    (differing + a.len().abs_diff(b.len())) as f32
+}
+
+#[derive(Debug, Clone)]
+pub struct LengthParams {
+    pub length: usize,
+}
+
+impl Default for LengthParams {
+    fn default() -> Self {
+        LengthParams { length: 16 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundedParams<T> {
+    pub length: usize,
+    pub min: T,
+    pub max: T,
+}
+
+impl Default for BoundedParams<i32> {
+    fn default() -> Self {
+        BoundedParams { length: 16, min: 0, max: 9 }
+    }
+}
+
+impl Default for BoundedParams<f32> {
+    fn default() -> Self {
+        BoundedParams { length: 16, min: -1.0, max: 1.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BitString {
+    pub bits: Vec<bool>,
+}
+
+impl Genome for BitString {
+    type Params = LengthParams;
+
+    fn random<R: Rng + ?Sized>(params: &LengthParams, rng: &mut R) -> Self {
+        BitString {
+            bits: (0..params.length).map(|_| rng.gen()).collect(),
+        }
+    }
+
+    // Slot operators only, anything else is one-point
+    fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Self,
+        operator: &CrossoverOperator,
+        _: &LengthParams,
+        rng: &mut R,
+    ) -> (Self, Self) {
+        let (bits1, bits2) = operator.slots(&self.bits, &other.bits, rng);
+        (BitString { bits: bits1 }, BitString { bits: bits2 })
+    }
+
+    fn mutate<R: Rng + ?Sized>(&mut self, mutation: &Mutation, _: &LengthParams, _: u32, rng: &mut R) {
+        mutation.bits(&mut self.bits, rng);
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        hamming(&self.bits, &other.bits)
+    }
+
+    fn hash_genes<H: Hasher>(&self, state: &mut H) {
+        self.bits.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntegerGenome {
+    pub alleles: Vec<i32>,
+}
+
+impl Genome for IntegerGenome {
+    type Params = BoundedParams<i32>;
+
+    fn random<R: Rng + ?Sized>(params: &BoundedParams<i32>, rng: &mut R) -> Self {
+        IntegerGenome {
+            alleles: (0..params.length).map(|_| rng.gen_range(params.min..=params.max)).collect(),
+        }
+    }
+
+    // Slot operators only, anything else is one-point
+    fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Self,
+        operator: &CrossoverOperator,
+        _: &BoundedParams<i32>,
+        rng: &mut R,
+    ) -> (Self, Self) {
+        let (alleles1, alleles2) = operator.slots(&self.alleles, &other.alleles, rng);
+        (IntegerGenome { alleles: alleles1 }, IntegerGenome { alleles: alleles2 })
+    }
+
+    // Real valued operators work on the alleles and round the result
+    fn mutate<R: Rng + ?Sized>(&mut self, mutation: &Mutation, params: &BoundedParams<i32>, _: u32, rng: &mut R) {
+        let mut values: Vec<f32> = self.alleles.iter().map(|&allele| allele as f32).collect();
+        mutation.real(&mut values, params.min as f32, params.max as f32, rng);
+        self.alleles = values.into_iter().map(|value| value.round() as i32).collect();
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        hamming(&self.alleles, &other.alleles)
+    }
+
+    fn hash_genes<H: Hasher>(&self, state: &mut H) {
+        self.alleles.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RealVector {
+    pub values: Vec<f32>,
+}
+
+impl Genome for RealVector {
+    type Params = BoundedParams<f32>;
+
+    fn random<R: Rng + ?Sized>(params: &BoundedParams<f32>, rng: &mut R) -> Self {
+        RealVector {
+            values: (0..params.length).map(|_| rng.gen_range(params.min..=params.max)).collect(),
+        }
+    }
+
+    // Children are kept inside the bounds
+    fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Self,
+        operator: &CrossoverOperator,
+        params: &BoundedParams<f32>,
+        rng: &mut R,
+    ) -> (Self, Self) {
+        let (values1, values2) = operator.real(&self.values, &other.values, rng);
+        let clamp = |values: Vec<f32>| RealVector {
+            values: values.into_iter().map(|v| v.clamp(params.min, params.max)).collect(),
+        };
+        (clamp(values1), clamp(values2))
+    }
+
+    fn mutate<R: Rng + ?Sized>(&mut self, mutation: &Mutation, params: &BoundedParams<f32>, _: u32, rng: &mut R) {
+        mutation.real(&mut self.values, params.min, params.max, rng);
+    }
+
+    // Euclidean distance
+    fn distance(&self, other: &Self) -> f32 {
+        self.values
+            .iter()
+            .zip(&other.values)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    fn hash_genes<H: Hasher>(&self, state: &mut H) {
+        for value in self.values.iter() {
+            value.to_bits().hash(state);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Permutation {
+    pub order: Vec<usize>,
+}
+
+impl Genome for Permutation {
+    type Params = LengthParams;
+
+    fn random<R: Rng + ?Sized>(params: &LengthParams, rng: &mut R) -> Self {
+        let mut order: Vec<usize> = (0..params.length).collect();
+        order.shuffle(rng);
+        Permutation { order }
+    }
+
+    // Permutation operators only, anything else is OX
+    fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Self,
+        operator: &CrossoverOperator,
+        _: &LengthParams,
+        rng: &mut R,
+    ) -> (Self, Self) {
+        let (order1, order2) = operator.permutation(&self.order, &other.order, rng);
+        (Permutation { order: order1 }, Permutation { order: order2 })
+    }
+
+    // Only the reordering operators keep a valid permutation
+    fn mutate<R: Rng + ?Sized>(&mut self, mutation: &Mutation, _: &LengthParams, _: u32, rng: &mut R) {
+        mutation.sequence(&mut self.order, rng);
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        hamming(&self.order, &other.order)
+    }
+
+    fn hash_genes<H: Hasher>(&self, state: &mut H) {
+        self.order.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::{seeded_rng, MutationOperator};
+
+    #[test]
+    fn bit_string_flips_and_counts_differing_bits() {
+        let mut rng = seeded_rng(1);
+        let params = LengthParams { length: 8 };
+        let genome = BitString::random(&params, &mut rng);
+        let mut flipped = genome.clone();
+        flipped.mutate(&Mutation::new(MutationOperator::BitFlip, 1.0), &params, 0, &mut rng);
+        assert!(flipped.bits.iter().zip(&genome.bits).all(|(a, b)| a != b));
+        assert_eq!(genome.distance(&flipped), 8.0);
+        assert_eq!(genome.distance(&genome), 0.0);
+
+        let (child1, child2) = genome.crossover(&flipped, &CrossoverOperator::Uniform, &params, &mut rng);
+        // Complementary children: every slot comes from one parent each
+        assert_eq!(child1.distance(&child2), 8.0);
+    }
+
+    #[test]
+    fn integer_mutation_stays_within_bounds() {
+        let mut rng = seeded_rng(2);
+        let params = BoundedParams { length: 32, min: -3, max: 3 };
+        let mut genome = IntegerGenome::random(&params, &mut rng);
+        let operators = [MutationOperator::Reset, MutationOperator::Creep { step: 5.0 }, MutationOperator::Gaussian { sigma: 4.0 }];
+        for operator in operators {
+            for _ in 0..20 {
+                genome.mutate(&Mutation::new(operator, 1.0), &params, 0, &mut rng);
+                assert!(genome.alleles.iter().all(|allele| (-3..=3).contains(allele)), "{operator:?}: {genome:?}");
+            }
+        }
+        let other = IntegerGenome { alleles: vec![0; 32] };
+        let zeros = genome.alleles.iter().filter(|&&allele| allele == 0).count();
+        assert_eq!(genome.distance(&other), (32 - zeros) as f32);
+    }
+
+    #[test]
+    fn real_vector_stays_within_bounds_and_measures_euclidean_distance() {
+        let mut rng = seeded_rng(3);
+        let params = BoundedParams { length: 16, min: -1.0, max: 1.0 };
+        let (mut a, b) = (RealVector::random(&params, &mut rng), RealVector::random(&params, &mut rng));
+        let in_bounds = |genome: &RealVector| genome.values.iter().all(|value| (-1.0..=1.0).contains(value));
+        for _ in 0..20 {
+            // Wide BLX-alpha children would leave the bounds without the clamp
+            let (child1, child2) = a.crossover(&b, &CrossoverOperator::BlendAlpha { alpha: 2.0 }, &params, &mut rng);
+            assert!(in_bounds(&child1) && in_bounds(&child2));
+            a.mutate(&Mutation::new(MutationOperator::Gaussian { sigma: 3.0 }, 1.0), &params, 0, &mut rng);
+            assert!(in_bounds(&a));
+        }
+
+        let origin = RealVector { values: vec![0.0, 0.0] };
+        let point = RealVector { values: vec![0.6, 0.8] };
+        assert!((origin.distance(&point) - 1.0).abs() < 1e-6);
+    }
+}
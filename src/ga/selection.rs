@@ -0,0 +1,212 @@
+// Parent selection strategies. Each one returns indices into the
+// individuals slice, consecutive indices are mated together.
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::population::Individual;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SelectionStrategy {
+    Tournament { k: usize },
+    RouletteWheel,
+    StochasticUniversal,
+    Rank,
+    // Only the best fraction of the population breeds
+    Truncation { fraction: f32 },
+    Boltzmann { temperature: f32 },
+}
+
+impl Default for SelectionStrategy {
+    fn default() -> Self {
+        SelectionStrategy::Tournament { k: 2 }
+    }
+}
+
+impl SelectionStrategy {
+    // One of each strategy with default parameters, used by the UI
+    pub const ALL: [SelectionStrategy; 6] = [
+        SelectionStrategy::Tournament { k: 2 },
+        SelectionStrategy::RouletteWheel,
+        SelectionStrategy::StochasticUniversal,
+        SelectionStrategy::Rank,
+        SelectionStrategy::Truncation { fraction: 0.5 },
+        SelectionStrategy::Boltzmann { temperature: 1.0 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SelectionStrategy::Tournament { .. } => "Tournament",
+            SelectionStrategy::RouletteWheel => "Roulette wheel",
+            SelectionStrategy::StochasticUniversal => "Stochastic universal sampling",
+            SelectionStrategy::Rank => "Rank",
+            SelectionStrategy::Truncation { .. } => "Truncation",
+            SelectionStrategy::Boltzmann { .. } => "Boltzmann",
+        }
+    }
+
+    pub fn same_kind(&self, other: &SelectionStrategy) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    pub fn select<G, R: Rng + ?Sized>(&self, individuals: &[Individual<G>], count: usize, rng: &mut R) -> Vec<usize> {
+        let fitness: Vec<f32> = individuals.iter().map(|individual| individual.fitness).collect();
+        self.select_by(&fitness, count, rng)
+    }
+
+    // Same as select, on fitness values other than the individuals' own,
+    // like the shared fitness of niching
+    pub fn select_by<R: Rng + ?Sized>(&self, fitness: &[f32], count: usize, rng: &mut R) -> Vec<usize> {
+        // SUS can't space out zero pointers
+        if fitness.is_empty() || count == 0 {
+            return Vec::new();
+        }
+
+        match *self {
+            SelectionStrategy::Tournament { k } => (0..count)
+                .map(|_| {
+                    (0..k.max(1))
+                        .map(|_| rng.gen_range(0..fitness.len()))
+                        .max_by(|&a, &b| fitness[a].total_cmp(&fitness[b]))
+                        .unwrap()
+                })
+                .collect(),
+            SelectionStrategy::RouletteWheel => {
+                let weights = shifted(fitness);
+                (0..count).map(|_| spin(&weights, rng.gen())).collect()
+            }
+            SelectionStrategy::StochasticUniversal => {
+                let weights = shifted(fitness);
+                let start: f32 = rng.gen_range(0.0..1.0 / count as f32);
+                (0..count)
+                    .map(|i| spin(&weights, start + i as f32 / count as f32))
+                    .collect()
+            }
+            SelectionStrategy::Rank => {
+                // Worst individual gets weight 1, best gets weight n
+                let order = ranked(fitness);
+                let mut weights = vec![0.0; fitness.len()];
+                for (rank, &index) in order.iter().rev().enumerate() {
+                    weights[index] = (rank + 1) as f32;
+                }
+                (0..count).map(|_| spin(&weights, rng.gen())).collect()
+            }
+            SelectionStrategy::Truncation { fraction } => {
+                let order = ranked(fitness);
+                let kept = ((fitness.len() as f32 * fraction).ceil() as usize).clamp(1, fitness.len());
+                (0..count).map(|_| order[rng.gen_range(0..kept)]).collect()
+            }
+            SelectionStrategy::Boltzmann { temperature } => {
+                let best = fitness.iter().copied().fold(f32::MIN, f32::max);
+                let weights: Vec<f32> = fitness
+                    .iter()
+                    .map(|f| ((f - best) / temperature.max(f32::EPSILON)).exp())
+                    .collect();
+                (0..count).map(|_| spin(&weights, rng.gen())).collect()
+            }
+        }
+    }
+}
+
+// Indices sorted from best to worst fitness
+fn ranked(fitness: &[f32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..fitness.len()).collect();
+    order.sort_by(|&a, &b| fitness[b].total_cmp(&fitness[a]));
+    order
+}
+
+// Roulette weights must not be negative
+fn shifted(fitness: &[f32]) -> Vec<f32> {
+    let min = fitness.iter().copied().fold(f32::MAX, f32::min).min(0.0);
+    fitness.iter().map(|f| f - min).collect()
+}
+
+// Picks the slot a pointer in [0, 1) falls into, uniform when all weights are 0
+fn spin(weights: &[f32], pointer: f32) -> usize {
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return ((pointer * weights.len() as f32) as usize).min(weights.len() - 1);
+    }
+
+    let mut target = pointer * total;
+    for (i, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return i;
+        }
+        target -= weight;
+    }
+    weights.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::seeded_rng;
+
+    fn counts(strategy: SelectionStrategy, fitness: &[f32], picks: usize) -> Vec<usize> {
+        let mut counts = vec![0; fitness.len()];
+        for index in strategy.select_by(fitness, picks, &mut seeded_rng(4)) {
+            counts[index] += 1;
+        }
+        counts
+    }
+
+    // Degenerate inputs still give `count` valid picks, or none at all
+    #[test]
+    fn every_strategy_handles_zero_and_equal_fitness() {
+        for strategy in SelectionStrategy::ALL {
+            for fitness in [vec![0.0; 5], vec![0.7; 5], vec![-1.0, 0.0, 2.0, -3.0, 1.0], vec![0.4]] {
+                let picks = strategy.select_by(&fitness, 9, &mut seeded_rng(1));
+                assert_eq!(picks.len(), 9, "{strategy:?}");
+                assert!(picks.iter().all(|&pick| pick < fitness.len()), "{strategy:?}");
+            }
+            assert!(strategy.select_by(&[], 4, &mut seeded_rng(1)).is_empty());
+            assert!(strategy.select_by(&[0.5, 0.6], 0, &mut seeded_rng(1)).is_empty());
+        }
+    }
+
+    // Every strategy favors the fit
+    #[test]
+    fn fitter_individuals_are_picked_more() {
+        let fitness = [0.1, 0.9, 0.5];
+        for strategy in SelectionStrategy::ALL {
+            let counts = counts(strategy, &fitness, 3000);
+            assert!(counts[1] > counts[2] && counts[2] >= counts[0], "{strategy:?}: {counts:?}");
+        }
+    }
+
+    #[test]
+    fn roulette_never_picks_zero_weights_unless_all_are_zero() {
+        assert_eq!(counts(SelectionStrategy::RouletteWheel, &[0.0, 1.0, 0.0], 500), vec![0, 500, 0]);
+        // All zero, the wheel is uniform
+        let uniform = counts(SelectionStrategy::RouletteWheel, &[0.0; 4], 4000);
+        assert!(uniform.iter().all(|&count| (800..1200).contains(&count)), "{uniform:?}");
+    }
+
+    // SUS pointers are evenly spaced, every individual gets its exact share
+    #[test]
+    fn stochastic_universal_gives_exact_shares() {
+        assert_eq!(counts(SelectionStrategy::StochasticUniversal, &[1.0, 3.0], 8), vec![2, 6]);
+        assert_eq!(counts(SelectionStrategy::StochasticUniversal, &[0.5; 4], 8), vec![2; 4]);
+    }
+
+    #[test]
+    fn truncation_keeps_only_the_best_fraction() {
+        let counts = counts(SelectionStrategy::Truncation { fraction: 0.5 }, &[0.2, 0.8, 0.6, 0.1], 1000);
+        assert_eq!((counts[0], counts[3]), (0, 0));
+        assert!(counts[1] > 0 && counts[2] > 0);
+    }
+
+    #[test]
+    fn extreme_tournaments_and_temperatures() {
+        let fitness = [0.3, 0.2, 0.9, 0.5];
+        // The best of 64 draws out of 4 is nearly always the best
+        let big_tournament = counts(SelectionStrategy::Tournament { k: 64 }, &fitness, 200);
+        assert_eq!(big_tournament[2], 200);
+        let cold = counts(SelectionStrategy::Boltzmann { temperature: 0.01 }, &fitness, 200);
+        assert_eq!(cold[2], 200);
+        // Rank gives the worst a weight of 1 out of 10
+        let rank = counts(SelectionStrategy::Rank, &fitness, 10_000);
+        assert!((800..1200).contains(&rank[1]), "{rank:?}");
+    }
+}
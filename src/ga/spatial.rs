@@ -0,0 +1,225 @@
+// Spatially structured (cellular) GA: individuals only mate with the ones
+// living near them, so local demes form and colors spread as waves.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Neighborhood {
+    // Anyone can mate with anyone
+    #[default]
+    Panmictic,
+    // Mates are within `radius` of the genome position
+    Radius { radius: f32 },
+    // Individuals sit on a cubic lattice `spacing` apart and mate within
+    // `range` lattice steps on every axis
+    Lattice { spacing: f32, range: u32 },
+}
+
+impl Neighborhood {
+    pub const ALL: [Neighborhood; 3] = [
+        Neighborhood::Panmictic,
+        Neighborhood::Radius { radius: 1.5 },
+        Neighborhood::Lattice { spacing: 1.0, range: 1 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Neighborhood::Panmictic => "Panmictic",
+            Neighborhood::Radius { .. } => "Radius",
+            Neighborhood::Lattice { .. } => "Lattice",
+        }
+    }
+
+    pub fn same_kind(&self, other: &Neighborhood) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    pub fn is_spatial(&self) -> bool {
+        *self != Neighborhood::Panmictic
+    }
+}
+
+// Lattice point of the `index`th individual out of `count`, the lattice is
+// centered on the origin
+pub fn lattice_point(index: usize, count: usize, spacing: f32) -> [f32; 3] {
+    let side = lattice_side(count);
+    let half = (side - 1) as f32 / 2.0;
+    let cell = [index % side, (index / side) % side, index / (side * side)];
+    cell.map(|coordinate| (coordinate as f32 - half) * spacing)
+}
+
+// Whether `position` is one of the points lattice_point gives for `count`
+pub fn on_lattice(position: [f32; 3], count: usize, spacing: f32) -> bool {
+    let half = (lattice_side(count) - 1) as f32 / 2.0;
+    position.iter().all(|&coordinate| {
+        let steps = coordinate / spacing + half;
+        (steps - steps.round()).abs() < 1e-3
+    })
+}
+
+fn lattice_side(count: usize) -> usize {
+    let mut side = (count as f64).cbrt().round() as usize;
+    while side * side * side < count {
+        side += 1;
+    }
+    side.max(1)
+}
+
+// Buckets positions into cubic cells so neighbors are found without looking
+// at the whole population
+pub struct SpatialHash<'a> {
+    cell: f32,
+    positions: &'a [[f32; 3]],
+    cells: HashMap<[i32; 3], Vec<usize>>,
+}
+
+impl<'a> SpatialHash<'a> {
+    pub fn new(cell: f32, positions: &'a [[f32; 3]]) -> Self {
+        let mut hash = SpatialHash {
+            cell,
+            positions,
+            cells: HashMap::new(),
+        };
+        for (index, &position) in positions.iter().enumerate() {
+            hash.cells.entry(hash.key(position)).or_default().push(index);
+        }
+        hash
+    }
+
+    fn key(&self, position: [f32; 3]) -> [i32; 3] {
+        position.map(|coordinate| (coordinate / self.cell).floor() as i32)
+    }
+
+    // Indices of the positions within `radius` of positions[index], itself
+    // excluded. `cube` measures the distance on the largest axis, like a
+    // lattice neighborhood, instead of a straight line.
+    pub fn neighbors(&self, index: usize, radius: f32, cube: bool) -> Vec<usize> {
+        let center = self.positions[index];
+        let reach = (radius / self.cell).ceil() as i32;
+        let [x, y, z] = self.key(center);
+        let mut found = Vec::new();
+        for dx in -reach..=reach {
+            for dy in -reach..=reach {
+                for dz in -reach..=reach {
+                    let Some(cell) = self.cells.get(&[x + dx, y + dy, z + dz]) else {
+                        continue;
+                    };
+                    for &other in cell {
+                        if other == index {
+                            continue;
+                        }
+                        let offset: [f32; 3] = std::array::from_fn(|axis| self.positions[other][axis] - center[axis]);
+                        let distance = if cube {
+                            offset.iter().fold(0.0f32, |max, value| max.max(value.abs()))
+                        } else {
+                            offset.iter().map(|value| value * value).sum::<f32>().sqrt()
+                        };
+                        // A little slack so lattice points right on the edge count
+                        if distance <= radius + 1e-4 {
+                            found.push(other);
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::seeded_rng;
+    use rand::Rng;
+
+    fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn radius_includes_its_edge() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, -1.01, 0.0], [1.0, 1.0, 0.0], [0.0, 0.0, 0.5]];
+        let hash = SpatialHash::new(1.0, &positions);
+        assert_eq!(sorted(hash.neighbors(0, 1.0, false)), [1, 4]);
+        // On the largest axis the diagonal is as close as the edge
+        assert_eq!(sorted(hash.neighbors(0, 1.0, true)), [1, 3, 4]);
+        assert_eq!(sorted(hash.neighbors(0, 1.5, false)), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn neighbors_across_cell_boundaries() {
+        let positions = [[0.99, 0.0, 0.0], [1.01, 0.0, 0.0], [-0.01, 0.0, 0.0], [0.01, 0.0, 0.0]];
+        let hash = SpatialHash::new(1.0, &positions);
+        assert_eq!(hash.neighbors(0, 0.05, false), [1]);
+        assert_eq!(hash.neighbors(2, 0.05, false), [3]);
+    }
+
+    // Same answer as looking at every position, whatever the cell size
+    #[test]
+    fn neighbors_match_brute_force() {
+        let mut rng = seeded_rng(3);
+        let positions: Vec<[f32; 3]> = (0..300).map(|_| [(); 3].map(|_| rng.gen_range(-5.0..5.0))).collect();
+        for (cell, radius) in [(1.0, 1.0), (0.5, 1.2), (2.0, 0.7)] {
+            let hash = SpatialHash::new(cell, &positions);
+            for cube in [false, true] {
+                for index in [0, 17, 299] {
+                    let center = positions[index];
+                    let expected: Vec<usize> = (0..positions.len())
+                        .filter(|&other| other != index)
+                        .filter(|&other| {
+                            let offset = std::array::from_fn::<f32, 3, _>(|axis| positions[other][axis] - center[axis]);
+                            let distance = if cube {
+                                offset.iter().fold(0.0f32, |max, value| max.max(value.abs()))
+                            } else {
+                                offset.iter().map(|value| value * value).sum::<f32>().sqrt()
+                            };
+                            distance <= radius
+                        })
+                        .collect();
+                    assert_eq!(sorted(hash.neighbors(index, radius, cube)), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lattice_points_are_distinct_and_centered() {
+        let points: Vec<[f32; 3]> = (0..27).map(|index| lattice_point(index, 27, 2.0)).collect();
+        assert_eq!(points[0], [-2.0, -2.0, -2.0]);
+        assert_eq!(points[13], [0.0, 0.0, 0.0]);
+        assert_eq!(points[26], [2.0, 2.0, 2.0]);
+        for (index, point) in points.iter().enumerate() {
+            assert!(on_lattice(*point, 27, 2.0));
+            assert!(!points[..index].contains(point));
+        }
+
+        // 10 doesn't fill a 2x2x2 lattice, so the side is 3
+        assert_eq!(lattice_point(9, 10, 1.0), [-1.0, -1.0, 0.0]);
+        assert_eq!(lattice_point(0, 8, 1.0), [-0.5, -0.5, -0.5]);
+        assert_eq!(lattice_point(0, 1, 1.0), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn on_lattice_refuses_points_between_nodes() {
+        assert!(on_lattice([1.0, 0.0, -1.0], 27, 1.0));
+        assert!(!on_lattice([0.3, 0.0, 0.0], 27, 1.0));
+        // An even side puts the nodes on half steps
+        assert!(on_lattice([0.5, -0.5, 0.5], 8, 1.0));
+        assert!(!on_lattice([0.0, 0.0, 0.0], 8, 1.0));
+    }
+
+    // A lattice range counts steps on every axis, diagonals included
+    #[test]
+    fn lattice_range_is_a_cube() {
+        let positions: Vec<[f32; 3]> = (0..125).map(|index| lattice_point(index, 125, 1.5)).collect();
+        let center = positions.iter().position(|&point| point == [0.0, 0.0, 0.0]).unwrap();
+        let hash = SpatialHash::new(1.5, &positions);
+        assert_eq!(hash.neighbors(center, 1.5, true).len(), 26);
+        assert_eq!(hash.neighbors(center, 3.0, true).len(), 124);
+        // Only the 6 faces in a straight line
+        assert_eq!(hash.neighbors(center, 1.5, false).len(), 6);
+        assert_eq!(hash.neighbors(0, 1.5, true).len(), 7);
+    }
+}
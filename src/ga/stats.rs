@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::genome::{CubeGenome, Genome, COLOR_GROUPS};
+use super::population::Population;
+
+pub const COLOR_NAMES: [&str; COLOR_GROUPS as usize] = ["red", "green", "blue", "yellow", "white"];
+
+// Summary of one generation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationStats {
+    pub generation: u32,
+    pub population: usize,
+    pub births: usize,
+    pub deaths: usize,
+    pub best: f32,
+    pub mean: f32,
+    pub median: f32,
+    pub worst: f32,
+    pub std_dev: f32,
+    // Number of chromosome genes in every color group, indexed like COLOR_NAMES
+    pub color_counts: Vec<usize>,
+}
+
+impl GenerationStats {
+    pub fn new<G: Genome>(population: &Population<G>) -> Self {
+        let mut fitness: Vec<f32> = population.individuals.iter().map(|individual| individual.fitness).collect();
+        fitness.sort_by(f32::total_cmp);
+
+        let mut stats = GenerationStats {
+            generation: population.generation,
+            population: fitness.len(),
+            births: population.births,
+            deaths: population.deaths,
+            ..Default::default()
+        };
+        if fitness.is_empty() {
+            return stats;
+        }
+
+        let n = fitness.len();
+        stats.best = fitness[n - 1];
+        stats.worst = fitness[0];
+        stats.mean = fitness.iter().sum::<f32>() / n as f32;
+        stats.median = if n.is_multiple_of(2) {
+            (fitness[n / 2 - 1] + fitness[n / 2]) / 2.0
+        } else {
+            fitness[n / 2]
+        };
+        stats.std_dev = (fitness.iter().map(|f| (f - stats.mean).powi(2)).sum::<f32>() / n as f32).sqrt();
+        stats
+    }
+}
+
+impl Population<CubeGenome> {
+    // Generation stats including the color group counts
+    pub fn stats(&self) -> GenerationStats {
+        let mut stats = GenerationStats::new(self);
+        stats.color_counts = vec![0; COLOR_NAMES.len()];
+        for individual in self.individuals.iter() {
+            for &gene in individual.genome.genes.iter() {
+                stats.color_counts[(gene as usize).min(COLOR_NAMES.len() - 1)] += 1;
+            }
+        }
+        stats
+    }
+}
+
+// Every generation of one run, with the seed that produced it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsHistory {
+    pub seed: u64,
+    pub records: Vec<GenerationStats>,
+}
+
+// One exported line, the seed is repeated so files can be concatenated
+#[derive(Serialize)]
+struct Row<'a> {
+    seed: u64,
+    #[serde(flatten)]
+    stats: &'a GenerationStats,
+}
+
+impl StatsHistory {
+    pub fn new(seed: u64) -> Self {
+        StatsHistory {
+            seed,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, stats: GenerationStats) {
+        self.records.push(stats);
+    }
+
+    pub fn latest(&self) -> Option<&GenerationStats> {
+        self.records.last()
+    }
+
+    pub fn write_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "seed,generation,population,births,deaths,best,mean,median,worst,std_dev")?;
+        for name in COLOR_NAMES {
+            write!(file, ",{name}")?;
+        }
+        writeln!(file)?;
+
+        for stats in self.records.iter() {
+            write!(
+                file,
+                "{},{},{},{},{},{},{},{},{},{}",
+                self.seed,
+                stats.generation,
+                stats.population,
+                stats.births,
+                stats.deaths,
+                stats.best,
+                stats.mean,
+                stats.median,
+                stats.worst,
+                stats.std_dev
+            )?;
+            for i in 0..COLOR_NAMES.len() {
+                write!(file, ",{}", stats.color_counts.get(i).copied().unwrap_or(0))?;
+            }
+            writeln!(file)?;
+        }
+        file.flush()
+    }
+
+    pub fn write_jsonl(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        for stats in self.records.iter() {
+            serde_json::to_writer(&mut file, &Row { seed: self.seed, stats })?;
+            writeln!(file)?;
+        }
+        file.flush()
+    }
+
+    // JSON Lines when the extension is .jsonl, CSV otherwise
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        if path.extension().is_some_and(|extension| extension == "jsonl") {
+            self.write_jsonl(path)
+        } else {
+            self.write_csv(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::seeded_rng;
+
+    fn stats(fitness: &[f32]) -> GenerationStats {
+        let mut population = Population::random_cubes(fitness.len(), &mut seeded_rng(2));
+        for (individual, &value) in population.individuals.iter_mut().zip(fitness) {
+            individual.fitness = value;
+        }
+        population.stats()
+    }
+
+    #[test]
+    fn median_of_odd_and_even_sizes() {
+        assert_eq!(stats(&[0.9, 0.1, 0.5]).median, 0.5);
+        assert_eq!(stats(&[0.9, 0.1, 0.5, 0.3]).median, 0.4);
+        assert_eq!(stats(&[0.7]).median, 0.7);
+    }
+
+    #[test]
+    fn summary_of_a_generation() {
+        let stats = stats(&[0.2, 0.4, 0.4, 0.4, 0.5, 0.5, 0.7, 0.9]);
+        assert_eq!((stats.best, stats.worst, stats.population), (0.9, 0.2, 8));
+        assert!((stats.mean - 0.5).abs() < 1e-6);
+        assert!((stats.std_dev - 0.2).abs() < 1e-6);
+        assert_eq!(stats.color_counts.iter().sum::<usize>(), 8 * 5);
+
+        let empty = GenerationStats::new(&Population::random_cubes(0, &mut seeded_rng(2)));
+        assert_eq!((empty.population, empty.best, empty.std_dev), (0, 0.0, 0.0));
+    }
+
+    fn history() -> StatsHistory {
+        let mut history = StatsHistory::new(77);
+        for (generation, fitness) in [[0.1, 0.3, 0.2], [0.25, 0.5, 0.75]].iter().enumerate() {
+            let mut stats = stats(fitness);
+            stats.generation = generation as u32;
+            stats.births = generation * 3;
+            history.push(stats);
+        }
+        history
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let history = history();
+        let path = std::env::temp_dir().join(format!("algogen_stats_{}.csv", std::process::id()));
+        history.write(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut lines = text.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        let summary = ["seed", "generation", "population", "births", "deaths"];
+        let fitness = ["best", "mean", "median", "worst", "std_dev"];
+        assert_eq!(header[..5], summary);
+        assert_eq!(header[5..10], fitness);
+        assert_eq!(header[10..], COLOR_NAMES);
+        for (line, stats) in lines.zip(history.records.iter()) {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields.len(), header.len());
+            assert_eq!(fields[0].parse::<u64>().unwrap(), 77);
+            assert_eq!(fields[1].parse::<u32>().unwrap(), stats.generation);
+            assert_eq!(fields[3].parse::<usize>().unwrap(), stats.births);
+            let values: Vec<f32> = fields[5..10].iter().map(|field| field.parse().unwrap()).collect();
+            assert_eq!(values, [stats.best, stats.mean, stats.median, stats.worst, stats.std_dev]);
+            let counts: Vec<usize> = fields[10..].iter().map(|field| field.parse().unwrap()).collect();
+            assert_eq!(counts, stats.color_counts);
+        }
+    }
+
+    #[test]
+    fn jsonl_round_trip() {
+        let history = history();
+        let path = std::env::temp_dir().join(format!("algogen_stats_{}.jsonl", std::process::id()));
+        history.write(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let rows: Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(rows.len(), history.records.len());
+        for (row, stats) in rows.into_iter().zip(history.records.iter()) {
+            assert_eq!(row["seed"], 77);
+            let read: GenerationStats = serde_json::from_value(row).unwrap();
+            assert_eq!(format!("{read:?}"), format!("{stats:?}"));
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use super::genome::{CubeGenome, COLOR_GROUPS, GENE_COUNT};
+use super::population::Population;
+
+// When a run should stop, every criterion left out is ignored. A run always
+// stops once the population is extinct.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminationCriteria {
+    pub max_generations: Option<u32>,
+    // Stop once the best individual reaches this fitness
+    pub target_fitness: Option<f32>,
+    // Stop after this many generations without a better best fitness
+    pub stagnation: Option<u32>,
+    // Stop after this many seconds of running
+    pub max_seconds: Option<f64>,
+    // Stop once on every gene one color is carried by at least this share
+    // of the population, 1.0 meaning the whole population
+    pub allele_fixation: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    MaxGenerations(u32),
+    TargetFitness(f32),
+    Stagnation(u32),
+    WallClock(f64),
+    Extinct,
+    AlleleFixation(f32),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::MaxGenerations(generations) => write!(f, "reached {generations} generations"),
+            StopReason::TargetFitness(fitness) => write!(f, "best fitness reached the target of {fitness}"),
+            StopReason::Stagnation(generations) => {
+                write!(f, "best fitness did not improve for {generations} generations")
+            }
+            StopReason::WallClock(seconds) => write!(f, "ran for the {seconds} second limit"),
+            StopReason::Extinct => write!(f, "population went extinct"),
+            StopReason::AlleleFixation(share) => {
+                write!(f, "every gene is fixed on one color in {:.0}% of the population", share * 100.0)
+            }
+        }
+    }
+}
+
+// Best fitness seen so far and the generation it was reached, saved in
+// checkpoints so a resumed run keeps counting stagnation
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct TerminationState {
+    // None until the first check, JSON has no infinity
+    pub best: Option<f32>,
+    pub improved_at: u32,
+}
+
+// Follows a run generation after generation to tell when it should stop
+#[derive(Debug, Clone)]
+pub struct TerminationCheck {
+    pub criteria: TerminationCriteria,
+    state: TerminationState,
+}
+
+impl TerminationCheck {
+    pub fn new(criteria: TerminationCriteria) -> Self {
+        TerminationCheck {
+            criteria,
+            state: TerminationState::default(),
+        }
+    }
+
+    pub fn state(&self) -> TerminationState {
+        self.state
+    }
+
+    pub fn restore(&mut self, state: TerminationState) {
+        self.state = state;
+    }
+
+    // Forgets the best fitness, for when the fitness function changes and
+    // old scores no longer compare. Stagnation counts from the next check.
+    pub fn reset(&mut self) {
+        self.state = TerminationState::default();
+    }
+
+    // `elapsed` is the time spent running so far
+    pub fn check(&mut self, population: &Population<CubeGenome>, elapsed: Duration) -> Option<StopReason> {
+        if population.individuals.is_empty() {
+            return Some(StopReason::Extinct);
+        }
+
+        let best = population.individuals.iter().map(|individual| individual.fitness).fold(f32::NEG_INFINITY, f32::max);
+        if self.state.best.is_none_or(|previous| best > previous) {
+            self.state.best = Some(best);
+            self.state.improved_at = population.generation;
+        }
+
+        let criteria = self.criteria;
+        if let Some(target) = criteria.target_fitness.filter(|&target| best >= target) {
+            return Some(StopReason::TargetFitness(target));
+        }
+        if let Some(max) = criteria.max_generations.filter(|&max| population.generation >= max) {
+            return Some(StopReason::MaxGenerations(max));
+        }
+        if let Some(limit) = criteria.stagnation.filter(|&limit| population.generation - self.state.improved_at >= limit) {
+            return Some(StopReason::Stagnation(limit));
+        }
+        if let Some(seconds) = criteria.max_seconds.filter(|&seconds| elapsed.as_secs_f64() >= seconds) {
+            return Some(StopReason::WallClock(seconds));
+        }
+        if let Some(share) = criteria.allele_fixation.filter(|&share| fixation(population) >= share) {
+            return Some(StopReason::AlleleFixation(share));
+        }
+        None
+    }
+}
+
+// Share of the population carrying the most common color, on the least
+// converged gene
+pub fn fixation(population: &Population<CubeGenome>) -> f32 {
+    let size = population.individuals.len();
+    if size == 0 {
+        return 0.0;
+    }
+    (0..GENE_COUNT)
+        .map(|locus| {
+            let mut counts = [0usize; COLOR_GROUPS as usize];
+            for individual in population.individuals.iter() {
+                counts[(individual.genome.genes[locus] as usize).min(counts.len() - 1)] += 1;
+            }
+            *counts.iter().max().unwrap() as f32 / size as f32
+        })
+        .fold(1.0, f32::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ga::seeded_rng;
+
+    fn population(best: f32, generation: u32) -> Population<CubeGenome> {
+        let mut population = Population::random_cubes(10, &mut seeded_rng(5));
+        for individual in population.individuals.iter_mut() {
+            individual.fitness = 0.1;
+        }
+        population.individuals[0].fitness = best;
+        population.generation = generation;
+        population
+    }
+
+    #[test]
+    fn stops_on_target_and_generation_count() {
+        let mut check = TerminationCheck::new(TerminationCriteria {
+            max_generations: Some(50),
+            target_fitness: Some(0.9),
+            ..Default::default()
+        });
+        assert_eq!(check.check(&population(0.5, 10), Duration::ZERO), None);
+        assert_eq!(check.check(&population(0.95, 11), Duration::ZERO), Some(StopReason::TargetFitness(0.9)));
+        assert_eq!(check.check(&population(0.5, 50), Duration::ZERO), Some(StopReason::MaxGenerations(50)));
+    }
+
+    // Stagnation counts from the last improvement, across a restore too
+    #[test]
+    fn stagnation_survives_a_restore() {
+        let criteria = TerminationCriteria {
+            stagnation: Some(5),
+            ..Default::default()
+        };
+        let mut check = TerminationCheck::new(criteria);
+        check.check(&population(0.5, 10), Duration::ZERO);
+        assert_eq!(check.check(&population(0.5, 14), Duration::ZERO), None);
+
+        let mut resumed = TerminationCheck::new(criteria);
+        resumed.restore(check.state());
+        assert_eq!(resumed.check(&population(0.5, 15), Duration::ZERO), Some(StopReason::Stagnation(5)));
+        // A better best fitness starts the count over
+        assert_eq!(check.check(&population(0.6, 15), Duration::ZERO), None);
+    }
+
+    // After a target change a lower best fitness is the new reference
+    #[test]
+    fn reset_forgets_the_best_fitness() {
+        let mut check = TerminationCheck::new(TerminationCriteria {
+            stagnation: Some(5),
+            ..Default::default()
+        });
+        check.check(&population(0.9, 10), Duration::ZERO);
+        check.reset();
+        assert_eq!(check.check(&population(0.4, 14), Duration::ZERO), None);
+        assert_eq!(check.state(), TerminationState { best: Some(0.4), improved_at: 14 });
+        assert_eq!(check.check(&population(0.4, 18), Duration::ZERO), None);
+        assert_eq!(check.check(&population(0.4, 19), Duration::ZERO), Some(StopReason::Stagnation(5)));
+    }
+
+    #[test]
+    fn stops_on_time_extinction_and_fixation() {
+        let mut check = TerminationCheck::new(TerminationCriteria {
+            max_seconds: Some(2.0),
+            allele_fixation: Some(1.0),
+            ..Default::default()
+        });
+        assert_eq!(check.check(&population(0.5, 1), Duration::from_secs(1)), None);
+        assert_eq!(check.check(&population(0.5, 2), Duration::from_secs(3)), Some(StopReason::WallClock(2.0)));
+
+        let mut fixed = population(0.5, 3);
+        for individual in fixed.individuals.iter_mut() {
+            individual.genome.genes = [2; GENE_COUNT];
+        }
+        assert_eq!(fixation(&fixed), 1.0);
+        assert_eq!(check.check(&fixed, Duration::ZERO), Some(StopReason::AlleleFixation(1.0)));
+
+        let mut extinct = population(0.5, 4);
+        extinct.individuals.clear();
+        assert_eq!(check.check(&extinct, Duration::ZERO), Some(StopReason::Extinct));
+    }
+}
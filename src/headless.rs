@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use algogen::ga::{self, FitnessCache, GenerationStats, HallOfFame, StatsHistory, TerminationCheck};
+
+use crate::checkpoint::Checkpoint;
+use crate::config::SimulationConfig;
+
+// Runs the GA core without any window or Bevy App, printing one line per
+// generation and writing the whole history to `output` (CSV, or JSON Lines
+// for a .jsonl path). The hall of fame goes next to it, see `fame_path`.
+// A resumed run continues from its checkpoint for
+// `generations` more generations. The termination criteria of the config
+// can end it earlier.
+pub fn run(
+    config: &SimulationConfig,
+    resume: Option<Checkpoint>,
+    generations: u32,
+    output: &Path,
+    save_checkpoint: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut termination = TerminationCheck::new(config.termination);
+    let (seed, mut rng, mut population, mut islands, mut history, mut hall_of_fame) = match resume {
+        Some(checkpoint) => {
+            println!("resuming from generation {}", checkpoint.population.generation);
+            let population = checkpoint.population();
+            let islands = checkpoint.archipelago(&population);
+            termination.restore(checkpoint.termination);
+            (checkpoint.seed, checkpoint.rng, population, islands, checkpoint.history, checkpoint.hall_of_fame)
+        }
+        None => {
+            let seed = config.seed.unwrap_or_else(rand::random);
+            let mut rng = ga::seeded_rng(seed);
+            let mut population = config.population(&mut rng);
+            let islands = config.archipelago(&mut population, &mut rng);
+            let mut hall_of_fame = HallOfFame::new(config.hall_of_fame_size);
+            hall_of_fame.update(&population);
+            (seed, rng, population, islands, StatsHistory::new(seed), hall_of_fame)
+        }
+    };
+
+    println!("seed {seed}");
+    if config.encounters.enabled {
+        println!("encounters need moving cubes, running generations instead");
+    }
+    if history.records.is_empty() {
+        report(population.stats(), &mut history);
+    }
+    let started = Instant::now();
+    let mut stopped = termination.check(&population, started.elapsed());
+    for _ in 0..generations {
+        if stopped.is_some() {
+            break;
+        }
+        match &mut islands {
+            Some(islands) => {
+                islands.evolve(&mut population, &mut rng);
+                islands.cull(&mut population, config.cull_fitness);
+            }
+            None => {
+                population.step(&mut rng);
+                population.cull(config.cull_fitness);
+            }
+        }
+        report(population.stats(), &mut history);
+        hall_of_fame.update(&population);
+        stopped = termination.check(&population, started.elapsed());
+    }
+    match stopped {
+        Some(reason) => println!("stopped: {reason}"),
+        None => println!("stopped: ran the {generations} generations asked for"),
+    }
+    println!(
+        "finished at generation {} after {:.1}s",
+        population.generation,
+        started.elapsed().as_secs_f64()
+    );
+    // Islands score their own individuals
+    let caches: Vec<&FitnessCache> = match &islands {
+        Some(islands) => islands.islands.iter().map(|island| &island.cache).collect(),
+        None => vec![&population.cache],
+    };
+    let hits: u64 = caches.iter().map(|cache| cache.hits).sum();
+    let lookups: u64 = caches.iter().map(|cache| cache.hits + cache.misses).sum();
+    println!(
+        "fitness cache: {:.0}% hits over {} evaluations, threads: {}",
+        hits as f64 * 100.0 / lookups.max(1) as f64,
+        lookups,
+        rayon::current_num_threads()
+    );
+
+    history.write(output)?;
+    println!("results written to {}", output.display());
+    let fame_path = fame_path(output);
+    hall_of_fame.write(&fame_path, seed)?;
+    for (index, island) in islands.iter().flat_map(|islands| islands.islands.iter()).enumerate() {
+        let stats = island.stats();
+        println!("island {index}: population {}  best {:.4}  mean {:.4}", stats.population, stats.best, stats.mean);
+    }
+    if !population.species.is_empty() {
+        println!("species: {}", population.species.len());
+    }
+    if let Some(best) = hall_of_fame.best() {
+        println!("best ever: fitness {:.4} from generation {}", best.fitness, best.generation);
+    }
+    println!("hall of fame written to {}", fame_path.display());
+    if let Some(path) = save_checkpoint {
+        Checkpoint::new(
+            config,
+            &population,
+            islands.as_ref(),
+            seed,
+            &rng,
+            &history,
+            &hall_of_fame,
+            termination.state(),
+            Vec::new(),
+        )
+        .save(path)?;
+        println!("checkpoint written to {}", path.display());
+    }
+    Ok(())
+}
+
+fn report(stats: GenerationStats, history: &mut StatsHistory) {
+    println!(
+        "gen {:>5}  population {:>5}  births {:>4}  deaths {:>4}  best {:.4}  mean {:.4}  median {:.4}  worst {:.4}  std {:.4}",
+        stats.generation,
+        stats.population,
+        stats.births,
+        stats.deaths,
+        stats.best,
+        stats.mean,
+        stats.median,
+        stats.worst,
+        stats.std_dev
+    );
+    history.push(stats);
+}
+
+// results.csv -> results_hall_of_fame.csv
+fn fame_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let mut name = format!("{stem}_hall_of_fame");
+    if let Some(extension) = output.extension() {
+        name = format!("{name}.{}", extension.to_string_lossy());
+    }
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes a run of `generations` to a temporary CSV and returns the
+    // history and hall of fame files
+    fn run_to_files(config: &SimulationConfig, generations: u32, name: &str) -> (String, String) {
+        let output = std::env::temp_dir().join(format!("algogen_headless_{name}_{}.csv", std::process::id()));
+        run(config, None, generations, &output, None).unwrap();
+        let fame = fame_path(&output);
+        let files = (std::fs::read_to_string(&output).unwrap(), std::fs::read_to_string(&fame).unwrap());
+        std::fs::remove_file(&output).unwrap();
+        std::fs::remove_file(&fame).unwrap();
+        files
+    }
+
+    #[test]
+    fn seeded_runs_write_the_same_history() {
+        let config = SimulationConfig {
+            seed: Some(21),
+            population_size: 60,
+            ..Default::default()
+        };
+        let (history, fame) = run_to_files(&config, 15, "first");
+        // A header, the starting population and one line per generation
+        assert_eq!(history.lines().count(), 17);
+        assert!(history.starts_with("seed,generation,"));
+        assert!(!fame.is_empty());
+        assert_eq!(run_to_files(&config, 15, "second"), (history, fame));
+    }
+
+    #[test]
+    fn fame_path_sits_next_to_the_output() {
+        assert_eq!(fame_path(Path::new("out/results.csv")), Path::new("out/results_hall_of_fame.csv"));
+        assert_eq!(fame_path(Path::new("run.jsonl")), Path::new("run_hall_of_fame.jsonl"));
+    }
+}
@@ -0,0 +1,454 @@
+// Instanced rendering of the population, used with render_mode = "Instanced".
+// Instead of a parent entity and 5 gene entities per cube, the cubes live in
+// flat arrays moved with rayon and are drawn in one call from two instance
+// buffers, after Bevy's shader_instancing example. The colors only go up to
+// the GPU when the swarm is rebuilt, the positions every frame.
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::core_3d::Transparent3d,
+    ecs::system::{lifetimeless::*, SystemParamItem},
+    pbr::{MeshPipeline, MeshPipelineKey, RenderMeshInstances, SetMeshBindGroup, SetMeshViewBindGroup},
+    prelude::*,
+    render::{
+        mesh::{GpuBufferInfo, GpuMesh, MeshVertexBufferLayoutRef},
+        render_asset::RenderAssets,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand, RenderCommandResult,
+            SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        view::{ExtractedView, NoFrustumCulling},
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+use algogen::ga::{self, CubeGenome, GENE_COUNT};
+
+use crate::config::{RenderMode, SimulationConfig};
+use crate::simulation::{
+    island_regions, move_cube, settle_cube, setup_cube_assets, CorePopulation, CubeAssets, Islands, SavedCubes,
+};
+use crate::world::place_in_region;
+
+const INSTANCING_SHADER: Handle<Shader> = Handle::weak_from_u128(0x5a1e_c0de_0c0b_e5a1_7e5d_0000_0000_0001);
+
+// Parent cube plus its gene cubes
+pub const INSTANCES_PER_CUBE: usize = 1 + GENE_COUNT;
+
+pub struct InstancingPlugin;
+
+impl Plugin for InstancingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CubeSwarm>()
+            .add_systems(Startup, spawn_swarm.after(setup_cube_assets).run_if(instanced))
+            .add_systems(Update, move_swarm.run_if(instanced))
+            .add_systems(PostUpdate, (sync_swarm, build_instances).chain().run_if(instanced));
+
+        // Nothing to draw in a run without a renderer, like the benchmark
+        if app.get_sub_app(RenderApp).is_none() {
+            return;
+        }
+        load_internal_asset!(app, INSTANCING_SHADER, "instancing.wgsl", Shader::from_wgsl);
+        app.sub_app_mut(RenderApp)
+            .add_render_command::<Transparent3d, DrawCubes>()
+            .init_resource::<SpecializedMeshPipelines<CubePipeline>>()
+            .init_resource::<InstanceBuffers>()
+            .add_systems(ExtractSchedule, extract_instances)
+            .add_systems(Render, queue_cubes.in_set(RenderSet::QueueMeshes));
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.init_resource::<CubePipeline>();
+        }
+    }
+}
+
+pub fn instanced(config: Res<SimulationConfig>) -> bool {
+    config.render_mode == RenderMode::Instanced
+}
+
+// Every cube of the scene, one slot per individual in population order
+#[derive(Resource, Default)]
+pub struct CubeSwarm {
+    pub ids: Vec<u64>,
+    pub genomes: Vec<CubeGenome>,
+    pub positions: Vec<Vec3>,
+    pub velocities: Vec<Vec3>,
+    pub islands: Vec<usize>,
+    // times the swarm was rebuilt from the population, once per generation
+    // or edit when change detection works
+    pub rebuilds: u64,
+}
+
+// The instances of every cube. What they look like is rebuilt with the
+// swarm, where they are every frame.
+#[derive(Component, Default)]
+pub struct InstanceMaterialData {
+    instances: Vec<InstanceData>,
+    // world position of the cube each instance belongs to
+    positions: Vec<Vec3>,
+    // the CubeSwarm::rebuilds `instances` was built for
+    rebuilds: u64,
+}
+
+impl InstanceMaterialData {
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct InstanceData {
+    // from the position of the cube
+    offset: Vec3,
+    scale: f32,
+    color: [f32; 4],
+}
+
+fn spawn_swarm(mut commands: Commands, assets: Res<CubeAssets>) {
+    commands.spawn((
+        assets.mesh.clone(),
+        SpatialBundle::INHERITED_IDENTITY,
+        InstanceMaterialData::default(),
+        // The instances are all over the world, not around the entity
+        NoFrustumCulling,
+    ));
+}
+
+// Rebuilds the swarm when the population changes. Surviving cubes keep their
+// position and velocity, new ones start at their genome position.
+fn sync_swarm(
+    mut commands: Commands,
+    population: Option<Res<CorePopulation>>,
+    mut swarm: ResMut<CubeSwarm>,
+    saved: Option<Res<SavedCubes>>,
+    config: Res<SimulationConfig>,
+    islands: Option<Res<Islands>>,
+) {
+    let Some(population) = population else {
+        return;
+    };
+    if !population.is_changed() {
+        return;
+    }
+
+    let previous: HashMap<u64, usize> = swarm.ids.iter().enumerate().map(|(slot, &id)| (id, slot)).collect();
+    let saved = saved.as_deref().map(|saved| &saved.0);
+    let island_of = islands.map(|islands| islands.island_of()).unwrap_or_default();
+    let island = |id: u64| island_of.get(&id).copied().unwrap_or_default();
+    let regions = island_regions(&config);
+    let old = &*swarm;
+    let max_velocity = config.max_velocity;
+    let (positions, velocities): (Vec<Vec3>, Vec<Vec3>) = population
+        .individuals
+        .par_iter()
+        .map(|individual| {
+            if let Some(cube) = saved.and_then(|saved| saved.get(&individual.id)) {
+                return (Vec3::from_array(cube.position), Vec3::from_array(cube.velocity));
+            }
+            if let Some(&slot) = previous.get(&individual.id) {
+                return (old.positions[slot], old.velocities[slot]);
+            }
+            let mut rng = rand::thread_rng();
+            let velocity = Vec3::new(
+                rng.gen_range(-max_velocity..max_velocity),
+                rng.gen_range(-max_velocity..max_velocity),
+                rng.gen_range(-max_velocity..max_velocity),
+            );
+            let region = regions[island(individual.id).min(regions.len() - 1)];
+            let position = place_in_region(Vec3::from_array(individual.genome.position), config.world_bounds, region);
+            (position, velocity)
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .unzip();
+
+    swarm.ids = population.individuals.iter().map(|individual| individual.id).collect();
+    swarm.genomes = population.individuals.iter().map(|individual| individual.genome.clone()).collect();
+    swarm.positions = positions;
+    swarm.velocities = velocities;
+    swarm.islands = swarm.ids.iter().map(|&id| island(id)).collect();
+    swarm.rebuilds += 1;
+    if saved.is_some() {
+        commands.remove_resource::<SavedCubes>();
+    }
+}
+
+fn move_swarm(
+    mut swarm: ResMut<CubeSwarm>,
+    time: Res<Time>,
+    config: Res<SimulationConfig>,
+    population: Option<Res<CorePopulation>>,
+) {
+    let (speed_multiplier, delta) = (config.speed_multiplier, time.delta_seconds());
+    let regions = island_regions(&config);
+    let spatial = population.is_some_and(|population| population.neighborhood.is_spatial());
+    let swarm = &mut *swarm;
+    swarm
+        .positions
+        .par_iter_mut()
+        .zip(swarm.velocities.par_iter_mut())
+        .zip(swarm.islands.par_iter().zip(swarm.genomes.par_iter()))
+        .for_each(|((position, velocity), (&island, genome))| {
+            let region = regions[island.min(regions.len() - 1)];
+            if spatial {
+                settle_cube(position, genome, config.world_bounds, delta, region);
+            } else {
+                move_cube(position, velocity, speed_multiplier, delta, region);
+            }
+        });
+}
+
+// Same layout as the entity cubes: a 0.3 parent with its genes in a row on +x
+fn build_instances(swarm: Res<CubeSwarm>, mut data: Query<&mut InstanceMaterialData>) {
+    let Ok(mut data) = data.get_single_mut() else {
+        return;
+    };
+    let data = &mut *data;
+    let count = swarm.ids.len() * INSTANCES_PER_CUBE;
+    if data.rebuilds != swarm.rebuilds || data.instances.len() != count {
+        data.instances.resize(count, InstanceData::zeroed());
+        data.instances.par_chunks_mut(INSTANCES_PER_CUBE).zip(swarm.genomes.par_iter()).for_each(|(cube, genome)| {
+            cube[0] = InstanceData {
+                offset: Vec3::ZERO,
+                scale: 0.3,
+                color: color(genome.color_group),
+            };
+            for (locus, &gene) in genome.genes.iter().enumerate() {
+                cube[1 + locus] = InstanceData {
+                    offset: Vec3::new(0.3 * (1.0 + locus as f32), 0.0, 0.0),
+                    scale: 0.15,
+                    color: color(gene),
+                };
+            }
+        });
+        data.rebuilds = swarm.rebuilds;
+    }
+    data.positions.resize(count, Vec3::ZERO);
+    data.positions
+        .par_chunks_mut(INSTANCES_PER_CUBE)
+        .zip(swarm.positions.par_iter())
+        .for_each(|(cube, &position)| cube.fill(position));
+}
+
+// The palette of CubeAssets, in the linear space the shader outputs
+fn color(color_group: u8) -> [f32; 4] {
+    let [r, g, b] = ga::group_color(color_group);
+    LinearRgba::from(Color::srgb(r, g, b)).to_f32_array()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_cubes(
+    transparent_3d_draw_functions: Res<DrawFunctions<Transparent3d>>,
+    cube_pipeline: Res<CubePipeline>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<CubePipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<GpuMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    buffers: Res<InstanceBuffers>,
+    mut transparent_render_phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
+    views: Query<(Entity, &ExtractedView)>,
+) {
+    let Some(entity) = buffers.entity.filter(|_| buffers.length > 0) else {
+        return;
+    };
+    let draw_cubes = transparent_3d_draw_functions.read().id::<DrawCubes>();
+    let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples());
+
+    for (view_entity, view) in views.iter() {
+        let Some(transparent_phase) = transparent_render_phases.get_mut(&view_entity) else {
+            continue;
+        };
+        let view_key = msaa_key | MeshPipelineKey::from_hdr(view.hdr);
+        let rangefinder = view.rangefinder3d();
+        let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(entity) else {
+            continue;
+        };
+        let Some(mesh) = meshes.get(mesh_instance.mesh_asset_id) else {
+            continue;
+        };
+        let key = view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology());
+        let pipeline = pipelines.specialize(&pipeline_cache, &cube_pipeline, key, &mesh.layout).unwrap();
+        transparent_phase.add(Transparent3d {
+            entity,
+            pipeline,
+            draw_function: draw_cubes,
+            distance: rangefinder.distance_translation(&mesh_instance.translation),
+            batch_range: 0..1,
+            extra_index: PhaseItemExtraIndex::NONE,
+        });
+    }
+}
+
+// The instance buffers of the swarm, kept from frame to frame and only
+// reallocated when the swarm outgrows them
+#[derive(Resource, Default)]
+struct InstanceBuffers {
+    entity: Option<Entity>,
+    instances: Option<Buffer>,
+    positions: Option<Buffer>,
+    capacity: usize,
+    length: usize,
+    // the CubeSwarm::rebuilds in `instances`
+    rebuilds: Option<u64>,
+}
+
+// Writes the swarm straight into the buffers, the instances only when the
+// swarm was rebuilt and the positions every frame since the cubes move
+fn extract_instances(
+    swarms: Extract<Query<(Entity, &InstanceMaterialData)>>,
+    mut buffers: ResMut<InstanceBuffers>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let buffers = &mut *buffers;
+    buffers.length = 0;
+    let Ok((entity, data)) = swarms.get_single() else {
+        return;
+    };
+    // An empty buffer can't be bound
+    if data.is_empty() {
+        return;
+    }
+
+    let grown = buffers.capacity < data.len();
+    if grown {
+        let capacity = data.len().next_power_of_two();
+        let buffer = |label, stride: usize| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: (capacity * stride) as u64,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        buffers.instances = Some(buffer("cube instance buffer", std::mem::size_of::<InstanceData>()));
+        buffers.positions = Some(buffer("cube position buffer", std::mem::size_of::<Vec3>()));
+        buffers.capacity = capacity;
+    }
+    let (Some(instances), Some(positions)) = (&buffers.instances, &buffers.positions) else {
+        return;
+    };
+    if grown || buffers.rebuilds != Some(data.rebuilds) {
+        render_queue.write_buffer(instances, 0, bytemuck::cast_slice(&data.instances));
+        buffers.rebuilds = Some(data.rebuilds);
+    }
+    render_queue.write_buffer(positions, 0, bytemuck::cast_slice(&data.positions));
+    buffers.entity = Some(entity);
+    buffers.length = data.len();
+}
+
+#[derive(Resource)]
+struct CubePipeline {
+    mesh_pipeline: MeshPipeline,
+}
+
+impl FromWorld for CubePipeline {
+    fn from_world(world: &mut World) -> Self {
+        CubePipeline {
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for CubePipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+        descriptor.vertex.shader = INSTANCING_SHADER;
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceData>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                // offset and scale, locations 0-2 are the mesh position, normal and uv
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 3,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: VertexFormat::Float32x4.size(),
+                    shader_location: 4,
+                },
+            ],
+        });
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vec3>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![VertexAttribute {
+                format: VertexFormat::Float32x3,
+                offset: 0,
+                shader_location: 5,
+            }],
+        });
+        descriptor.fragment.as_mut().unwrap().shader = INSTANCING_SHADER;
+        Ok(descriptor)
+    }
+}
+
+type DrawCubes = (SetItemPipeline, SetMeshViewBindGroup<0>, SetMeshBindGroup<1>, DrawMeshInstanced);
+
+struct DrawMeshInstanced;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawMeshInstanced {
+    type Param = (SRes<RenderAssets<GpuMesh>>, SRes<RenderMeshInstances>, SRes<InstanceBuffers>);
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        _entity: Option<()>,
+        (meshes, render_mesh_instances, buffers): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(item.entity()) else {
+            return RenderCommandResult::Failure;
+        };
+        let Some(gpu_mesh) = meshes.into_inner().get(mesh_instance.mesh_asset_id) else {
+            return RenderCommandResult::Failure;
+        };
+        let buffers = buffers.into_inner();
+        let (Some(instances), Some(positions)) = (&buffers.instances, &buffers.positions) else {
+            return RenderCommandResult::Failure;
+        };
+        let length = buffers.length as u32;
+
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, instances.slice(..));
+        pass.set_vertex_buffer(2, positions.slice(..));
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, 0..length);
+            }
+            GpuBufferInfo::NonIndexed => {
+                pass.draw(0..gpu_mesh.vertex_count, 0..length);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
@@ -0,0 +1,35 @@
+// Draws the cubes of the swarm, one instance per parent or gene cube
+#import bevy_pbr::view_transformations::position_world_to_clip
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+
+    @location(3) i_offset_scale: vec4<f32>,
+    @location(4) i_color: vec4<f32>,
+    @location(5) i_position: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    // The instances carry world positions, the transform of the swarm
+    // entity doesn't apply
+    let position = vertex.position * vertex.i_offset_scale.w + vertex.i_offset_scale.xyz + vertex.i_position;
+    var out: VertexOutput;
+    out.clip_position = position_world_to_clip(position);
+    // Cheap fixed light so the faces of a cube can be told apart
+    let light = 0.6 + 0.4 * max(dot(vertex.normal, normalize(vec3<f32>(0.4, 1.0, 0.6))), 0.0);
+    out.color = vec4<f32>(vertex.i_color.rgb * light, vertex.i_color.a);
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
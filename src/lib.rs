@@ -0,0 +1,3 @@
+// Library half of algogen: the headless GA core, usable from tests and
+// batch jobs without opening a window.
+pub mod ga;
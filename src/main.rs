@@ -1,77 +1,804 @@
+use algogen::ga::{
+    group_color, ColorMetric, CrossoverOperator, FitnessCache, GenerationStats, MigrantPolicy, MigrationTopology,
+    Mutation, MutationOperator, Neighborhood, Niching, Population, ReplacementModel, SelectionStrategy, COLOR_NAMES,
+};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use simulation::InitPlugin;
-use world::WorldPlugin;
-use crate::simulation::ColorGroup;
-use crate::simulation::GenerationNumber;
+use bevy_debug_grid::*;
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use bevy_fly_cam::FlyCamPlugin;
-use bevy_debug_grid::*;
-use bevy::prelude::Resource;
+use egui_plot::{Legend, Line, Plot};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::checkpoint::Checkpoint;
+use crate::config::SimulationConfig;
+use crate::encounter::{EncounterPlugin, Encounters};
+use crate::instancing::{CubeSwarm, InstanceMaterialData, InstancingPlugin};
+use crate::simulation::{
+    CheckpointRequest, CheckpointStatus, CorePopulation, GenerationHistory, GenerationNumber, GenerationRate,
+    HallOfFame, InitPlugin, Islands, ResumeCheckpoint, RunSummary, SimRng, SimulationState, StepGeneration,
+    TargetFitness, Termination,
+};
+use crate::world::{species_color, WorldPlugin};
 
+mod bench;
+mod checkpoint;
+mod config;
+mod encounter;
+mod headless;
+mod instancing;
 mod simulation;
 mod world;
 
 
+fn main() {
+    let args = CliArgs::parse().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+    let mut config = match &args.config {
+        Some(path) => SimulationConfig::load(path).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }),
+        None => SimulationConfig::default(),
+    };
+    // The command line wins over the config file
+    if args.seed.is_some() {
+        config.seed = args.seed;
+    }
+    // A resumed run keeps the settings it was saved with, except how it is
+    // drawn and how many threads it gets
+    let resume = args.resume.as_deref().map(|path| {
+        Checkpoint::load(path).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            std::process::exit(1);
+        })
+    });
+    if let Some(checkpoint) = &resume {
+        let (render_mode, threads) = (config.render_mode, config.threads);
+        config = checkpoint.config.clone();
+        config.render_mode = render_mode;
+        config.threads = threads;
+    }
+    if let Err(err) = config.init_threads() {
+        eprintln!("cannot start {} threads: {err}", config.threads.unwrap_or_default());
+        std::process::exit(1);
+    }
 
-#[derive(Resource)]
-struct SimulationState {
-    running: bool,
-}
+    if let Some(size) = args.bench {
+        if let Err(err) = bench::run(&config, size, args.generations) {
+            eprintln!("benchmark failed: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
-fn main() {
-    App::new()
-        .insert_resource(SimulationState { running: false })
+    if args.headless {
+        if let Err(err) = headless::run(&config, resume, args.generations, &args.output, args.save_checkpoint.as_deref()) {
+            eprintln!("headless run failed: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut app = App::new();
+    if let Some(checkpoint) = resume {
+        app.insert_resource(ResumeCheckpoint(checkpoint));
+    }
+    app.insert_resource(config)
         .add_plugins((
             DefaultPlugins,
             WorldPlugin,
             InitPlugin,
+            InstancingPlugin,
+            EncounterPlugin,
             FlyCamPlugin,
             DebugGridPlugin::with_floor_grid(),
             EguiPlugin,
         ))
-        .add_systems(Startup, setup)
-        .add_systems(Update, (control_window_system, simulation_system))
+        .add_systems(
+            Update,
+            (
+                control_window_system,
+                stats_window_system,
+                plots_window_system,
+                islands_window_system,
+                encounters_window_system,
+                debug_window_system,
+            ),
+        )
         .run();
 }
 
-fn setup(mut commands: Commands) {
-    commands.insert_resource(GenerationNumber { current_gen: 0 });
+struct CliArgs {
+    // --config <path>
+    config: Option<PathBuf>,
+    // --seed <u64>
+    seed: Option<u64>,
+    // --headless: run without a window
+    headless: bool,
+    // --generations <n>, headless only
+    generations: u32,
+    // --output <path>, headless only
+    output: PathBuf,
+    // --resume <path>: continue a saved checkpoint
+    resume: Option<PathBuf>,
+    // --save-checkpoint <path>: checkpoint written at the end, headless only
+    save_checkpoint: Option<PathBuf>,
+    // --bench <size>: time both render modes on this many individuals
+    // for --generations frames
+    bench: Option<usize>,
 }
 
-fn control_window_system(mut contexts: EguiContexts, mut state: ResMut<SimulationState>, generate_counter: Res<GenerationNumber>) {
+impl CliArgs {
+    fn parse() -> Result<Self, String> {
+        let mut parsed = CliArgs {
+            config: None,
+            seed: None,
+            headless: false,
+            generations: 100,
+            output: PathBuf::from("results.csv"),
+            resume: None,
+            save_checkpoint: None,
+            bench: None,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value after {arg}"));
+            match arg.as_str() {
+                "--config" => parsed.config = Some(PathBuf::from(value()?)),
+                "--seed" => {
+                    let seed = value()?;
+                    parsed.seed = Some(seed.parse().map_err(|_| format!("invalid seed: {seed}"))?);
+                }
+                "--headless" => parsed.headless = true,
+                "--generations" => {
+                    let generations = value()?;
+                    parsed.generations =
+                        generations.parse().map_err(|_| format!("invalid generation count: {generations}"))?;
+                }
+                "--output" => parsed.output = PathBuf::from(value()?),
+                "--resume" => parsed.resume = Some(PathBuf::from(value()?)),
+                "--save-checkpoint" => parsed.save_checkpoint = Some(PathBuf::from(value()?)),
+                "--bench" => {
+                    let size = value()?;
+                    parsed.bench = Some(size.parse().map_err(|_| format!("invalid population size: {size}"))?);
+                }
+                _ => return Err(format!("unknown argument: {arg}")),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+// Save and load buttons of the control window
+#[derive(SystemParam)]
+struct CheckpointControls<'w, 's> {
+    requests: EventWriter<'w, CheckpointRequest>,
+    status: Res<'w, CheckpointStatus>,
+    path: Local<'s, String>,
+}
+
+// Target fitness of the control window and what has to follow it
+#[derive(SystemParam)]
+struct FitnessControls<'w> {
+    target: ResMut<'w, TargetFitness>,
+    hall_of_fame: ResMut<'w, HallOfFame>,
+    termination: ResMut<'w, Termination>,
+}
+
+// Run, pause, step and speed buttons of the control window
+#[derive(SystemParam)]
+struct RunControls<'w> {
+    state: Res<'w, State<SimulationState>>,
+    next_state: ResMut<'w, NextState<SimulationState>>,
+    rate: ResMut<'w, GenerationRate>,
+    steps: EventWriter<'w, StepGeneration>,
+    summary: Option<Res<'w, RunSummary>>,
+}
+
+fn control_window_system(
+    mut contexts: EguiContexts,
+    mut run: RunControls,
+    generate_counter: Res<GenerationNumber>,
+    mut core: ResMut<CorePopulation>,
+    mut fitness: FitnessControls,
+    rng: Res<SimRng>,
+    mut checkpoint: CheckpointControls,
+) {
+    // The cube syncs rebuild whenever CorePopulation changes, so the widgets
+    // edit it without change detection and it's only flagged when a
+    // setting actually moved
+    let before = (EditedSettings::of(&core), fitness.target.0);
+    let population = core.bypass_change_detection();
     egui::Window::new("Control Window").show(contexts.ctx_mut(), |ui| {
         // Display the current generation number
         ui.label(format!("Current Generation: {}", generate_counter.current_gen));
+        ui.label(format!("Seed: {}", rng.seed));
+
+        ui.separator();
+        run_ui(ui, &mut run);
+
+        ui.separator();
+        fitness_ui(ui, &mut fitness, population);
+
+        ui.separator();
+        selection_ui(ui, &mut population.selection);
+
+        ui.separator();
+        replacement_ui(ui, population);
+
+        ui.separator();
+        let niching = population.niching;
+        neighborhood_ui(ui, &mut population.neighborhood, niching);
 
+        ui.separator();
+        let neighborhood = population.neighborhood;
+        niching_ui(ui, &mut population.niching, neighborhood);
+
+        ui.separator();
+        crossover_ui(ui, population);
+
+        ui.separator();
+        mutation_ui(ui, &mut population.mutations);
+
+        ui.separator();
+        checkpoint_ui(ui, &mut checkpoint);
+    });
+    if (EditedSettings::of(&core), fitness.target.0) != before {
+        core.set_changed();
+    }
+}
+
+// Every population setting the control window can edit
+#[derive(PartialEq)]
+struct EditedSettings {
+    size: usize,
+    elite_count: usize,
+    replacement: ReplacementModel,
+    selection: SelectionStrategy,
+    crossover: CrossoverOperator,
+    crossover_rate: f32,
+    two_offspring: bool,
+    mutations: Vec<Mutation>,
+    neighborhood: Neighborhood,
+    niching: Niching,
+}
+
+impl EditedSettings {
+    fn of(population: &Population) -> Self {
+        EditedSettings {
+            size: population.size,
+            elite_count: population.elite_count,
+            replacement: population.replacement,
+            selection: population.selection,
+            crossover: population.crossover,
+            crossover_rate: population.crossover_rate,
+            two_offspring: population.two_offspring,
+            mutations: population.mutations.clone(),
+            neighborhood: population.neighborhood,
+            niching: population.niching,
+        }
+    }
+}
+
+fn run_ui(ui: &mut egui::Ui, run: &mut RunControls) {
+    let state = *run.state.get();
+    if state == SimulationState::Finished {
+        if let Some(summary) = &run.summary {
+            ui.label(format!("Finished: {}", summary.reason));
+            ui.label(format!(
+                "Stopped at generation {} after {:.1}s, best fitness {:.3}",
+                summary.generation,
+                summary.elapsed.as_secs_f64(),
+                summary.best
+            ));
+        }
+        return;
+    }
+
+    ui.horizontal(|ui| {
         // Add a button to start/stop the simulation
-        if ui.button(if state.running { "Stop Simulation" } else { "Run Simulation" }).clicked() {
-            state.running = !state.running;
+        if state == SimulationState::Running {
+            if ui.button("Pause").clicked() {
+                run.next_state.set(SimulationState::Paused);
+            }
+        } else {
+            if ui.button("Run Simulation").clicked() {
+                run.next_state.set(SimulationState::Running);
+            }
+            if ui.button("Step").clicked() {
+                run.steps.send(StepGeneration);
+                run.next_state.set(SimulationState::Paused);
+            }
         }
+        ui.label(format!("{state:?}"));
     });
+
+    let mut rate = run.rate.0;
+    ui.add(egui::Slider::new(&mut rate, 0.5..=240.0).logarithmic(true).text("Generations / s"));
+    if rate != run.rate.0 {
+        run.rate.0 = rate;
+    }
+}
+
+fn checkpoint_ui(ui: &mut egui::Ui, checkpoint: &mut CheckpointControls) {
+    if checkpoint.path.is_empty() {
+        *checkpoint.path = "checkpoint.json".to_string();
+    }
+    ui.horizontal(|ui| {
+        ui.label("Checkpoint");
+        ui.text_edit_singleline(&mut *checkpoint.path);
+    });
+    ui.horizontal(|ui| {
+        let path = PathBuf::from(checkpoint.path.as_str());
+        if ui.button("Save").clicked() {
+            checkpoint.requests.send(CheckpointRequest::Save(path.clone()));
+        }
+        if ui.button("Load").clicked() {
+            checkpoint.requests.send(CheckpointRequest::Load(path));
+        }
+    });
+    if !checkpoint.status.0.is_empty() {
+        ui.label(checkpoint.status.0.as_str());
+    }
 }
 
-fn simulation_system(
-    state: Res<SimulationState>,
-    mut commands: Commands,
-    query: Query<(
-        Entity,
-        &Handle<StandardMaterial>,
-        Option<&Parent>,
-        Option<&Children>,
-        Option<&Transform>,
-        Option<&ColorGroup>,
-    )>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut generate_counter: ResMut<GenerationNumber>,
+// Latest generation stats, the hall of fame and their export
+fn stats_window_system(
+    mut contexts: EguiContexts,
+    history: Res<GenerationHistory>,
+    hall_of_fame: Res<HallOfFame>,
+    population: Res<CorePopulation>,
+    mut export_status: Local<String>,
 ) {
-    if state.running {
-        simulation::process_generation_system(
-            commands,
-            query,
-            materials,
-            meshes,
-            generate_counter,
-        );
+    egui::Window::new("Statistics").show(contexts.ctx_mut(), |ui| {
+        if let Some(stats) = history.latest() {
+            ui.label(format!("Population: {}  Births: {}  Deaths: {}", stats.population, stats.births, stats.deaths));
+            ui.label(format!(
+                "Fitness best {:.3}  mean {:.3}  median {:.3}  worst {:.3}  std-dev {:.3}",
+                stats.best, stats.mean, stats.median, stats.worst, stats.std_dev
+            ));
+        }
+
+        if !population.species.is_empty() {
+            ui.collapsing(format!("Species ({})", population.species.len()), |ui| {
+                let fitness: HashMap<u64, f32> =
+                    population.individuals.iter().map(|individual| (individual.id, individual.fitness)).collect();
+                egui::Grid::new("species").striped(true).show(ui, |ui| {
+                    ui.label("Species");
+                    ui.label("Members");
+                    ui.label("Best");
+                    ui.label("Pairs bred");
+                    ui.end_row();
+                    for species in population.species.iter() {
+                        let [r, g, b, _] = species_color(species.id).to_srgba().to_u8_array();
+                        ui.colored_label(egui::Color32::from_rgb(r, g, b), format!("{}", species.id));
+                        ui.label(format!("{}", species.members.len()));
+                        let best = species.members.iter().filter_map(|id| fitness.get(id)).copied().fold(f32::MIN, f32::max);
+                        ui.label(format!("{best:.3}"));
+                        ui.label(format!("{}", species.offspring));
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+
+        ui.collapsing("Hall of fame", |ui| {
+            egui::Grid::new("hall_of_fame").striped(true).show(ui, |ui| {
+                ui.label("#");
+                ui.label("Fitness");
+                ui.label("Generation");
+                ui.label("Genes");
+                ui.end_row();
+                for (rank, fame) in hall_of_fame.entries.iter().enumerate() {
+                    ui.label(format!("{}", rank + 1));
+                    ui.label(format!("{:.3}", fame.fitness));
+                    ui.label(format!("{}", fame.generation));
+                    let genes: Vec<&str> = fame
+                        .genome
+                        .genes
+                        .iter()
+                        .map(|&gene| COLOR_NAMES[(gene as usize).min(COLOR_NAMES.len() - 1)])
+                        .collect();
+                    ui.label(genes.join(" "));
+                    ui.end_row();
+                }
+            });
+        });
+
+        ui.horizontal(|ui| {
+            for (extension, fame_path) in [("csv", "hall_of_fame.csv"), ("jsonl", "hall_of_fame.jsonl")] {
+                let path = format!("generation_stats.{extension}");
+                if ui.button(format!("Export {extension}")).clicked() {
+                    let written = history
+                        .write(Path::new(&path))
+                        .and_then(|()| hall_of_fame.write(Path::new(fame_path), history.seed));
+                    *export_status = match written {
+                        Ok(()) => format!("Wrote {path} and {fame_path}"),
+                        Err(err) => format!("Export failed: {err}"),
+                    };
+                }
+            }
+        });
+        if !export_status.is_empty() {
+            ui.label(export_status.as_str());
+        }
+    });
+}
+
+// Per-island stats and the migration settings, only shown in an island run
+fn islands_window_system(mut contexts: EguiContexts, islands: Option<ResMut<Islands>>) {
+    let Some(mut islands) = islands else {
+        return;
+    };
+    egui::Window::new("Islands").show(contexts.ctx_mut(), |ui| {
+        let migration = &mut islands.migration;
+        egui::ComboBox::from_label("Topology")
+            .selected_text(migration.topology.name())
+            .show_ui(ui, |ui| {
+                for topology in MigrationTopology::ALL {
+                    ui.selectable_value(&mut migration.topology, topology, topology.name());
+                }
+            });
+        egui::ComboBox::from_label("Migrants")
+            .selected_text(migration.policy.name())
+            .show_ui(ui, |ui| {
+                for policy in MigrantPolicy::ALL {
+                    ui.selectable_value(&mut migration.policy, policy, policy.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut migration.interval, 1..=100).text("Generations between migrations"));
+        ui.add(egui::Slider::new(&mut migration.migrants, 0..=20).text("Migrants per destination"));
+        ui.label(format!("Last migration moved {} individuals", islands.migrants.len()));
+
+        ui.separator();
+        egui::Grid::new("islands").striped(true).show(ui, |ui| {
+            ui.label("Island");
+            ui.label("Population");
+            ui.label("Best");
+            ui.label("Mean");
+            ui.end_row();
+            for (index, island) in islands.islands.iter().enumerate() {
+                let stats = island.stats();
+                ui.label(format!("{}", index + 1));
+                ui.label(format!("{}", stats.population));
+                ui.label(format!("{:.3}", stats.best));
+                ui.label(format!("{:.3}", stats.mean));
+                ui.end_row();
+            }
+        });
+    });
+}
+
+// Encounter settings and mating counts, only shown in encounter mode
+fn encounters_window_system(mut contexts: EguiContexts, encounters: Option<ResMut<Encounters>>) {
+    let Some(mut encounters) = encounters else {
+        return;
+    };
+    egui::Window::new("Encounters").show(contexts.ctx_mut(), |ui| {
+        let settings = &mut encounters.settings;
+        ui.add(egui::Slider::new(&mut settings.maturity, 0.0..=30.0).text("Maturity (s)"));
+        ui.add(egui::Slider::new(&mut settings.cooldown, 0.0..=60.0).text("Cooldown (s)"));
+        ui.add(egui::Slider::new(&mut settings.choosiness, 0.0..=10.0).text("Choosiness"));
+        ui.label(format!("Matings: {}  Children kept: {}", encounters.matings, encounters.births));
+    });
+}
+
+// Live charts of the generation history
+fn plots_window_system(mut contexts: EguiContexts, history: Res<GenerationHistory>) {
+    egui::Window::new("Evolution").default_open(false).show(contexts.ctx_mut(), |ui| {
+        let records = &history.records;
+        let points = |value: fn(&GenerationStats) -> f64| -> Vec<[f64; 2]> {
+            records.iter().map(|stats| [stats.generation as f64, value(stats)]).collect()
+        };
+
+        ui.label("Fitness");
+        Plot::new("fitness_plot")
+            .height(150.0)
+            .include_y(0.0)
+            .include_y(1.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(points(|stats| stats.best as f64)).name("Best"));
+                plot_ui.line(Line::new(points(|stats| stats.mean as f64)).name("Mean"));
+            });
+
+        // Share of every color among all the genes as a stacked area chart:
+        // each color's line is the running total up to it, filled down to 0,
+        // so its band lies between its line and the one below
+        ui.label("Color frequencies");
+        let mut totals = vec![0.0; records.len()];
+        let mut bands: Vec<Line> = Vec::new();
+        for (group, name) in COLOR_NAMES.iter().enumerate() {
+            let line: Vec<[f64; 2]> = records
+                .iter()
+                .zip(totals.iter_mut())
+                .map(|(stats, total)| {
+                    let genes: usize = stats.color_counts.iter().sum();
+                    let count = stats.color_counts.get(group).copied().unwrap_or(0);
+                    *total += if genes == 0 { 0.0 } else { count as f64 / genes as f64 };
+                    [stats.generation as f64, *total]
+                })
+                .collect();
+            let [r, g, b] = group_color(group as u8).map(|channel| (channel * 255.0) as u8);
+            bands.push(Line::new(line).name(name).color(egui::Color32::from_rgb(r, g, b)).fill(0.0));
+        }
+        Plot::new("colors_plot")
+            .height(150.0)
+            .include_y(0.0)
+            .include_y(1.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                // Top band first, the ones below are drawn over its fill
+                for band in bands.into_iter().rev() {
+                    plot_ui.line(band);
+                }
+            });
+
+        ui.label("Population size");
+        Plot::new("population_plot").height(120.0).include_y(0.0).show(ui, |plot_ui| {
+            plot_ui.line(Line::new(points(|stats| stats.population as f64)).name("Population"));
+        });
+    });
+}
+
+// Live asset and entity counts, they should stay flat over long runs
+#[allow(clippy::too_many_arguments)]
+fn debug_window_system(
+    mut contexts: EguiContexts,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    entities: Query<Entity>,
+    instances: Query<&InstanceMaterialData>,
+    population: Res<CorePopulation>,
+    islands: Option<Res<Islands>>,
+    swarm: Res<CubeSwarm>,
+) {
+    egui::Window::new("Debug").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label(format!("Meshes: {}", meshes.len()));
+        ui.label(format!("Materials: {}", materials.len()));
+        ui.label(format!("Entities: {}", entities.iter().count()));
+        ui.label(format!("Instances: {}", instances.iter().map(|data| data.len()).sum::<usize>()));
+        // Should follow the generation count, not the frame count
+        ui.label(format!("Swarm rebuilds: {}", swarm.rebuilds));
+        // Islands score their own individuals
+        let caches: Vec<&FitnessCache> = match &islands {
+            Some(islands) => islands.islands.iter().map(|island| &island.cache).collect(),
+            None => vec![&population.cache],
+        };
+        let hits: u64 = caches.iter().map(|cache| cache.hits).sum();
+        let lookups: u64 = caches.iter().map(|cache| cache.hits + cache.misses).sum();
+        ui.label(format!(
+            "Fitness cache: {} scores, {:.0}% hits",
+            caches.iter().map(|cache| cache.len()).sum::<usize>(),
+            hits as f64 * 100.0 / lookups.max(1) as f64
+        ));
+        ui.label(format!("Threads: {}", rayon::current_num_threads()));
+    });
+}
+
+// Target color and metric, the population and the hall of fame are
+// re-scored when they change and termination forgets the old best fitness
+fn fitness_ui(ui: &mut egui::Ui, controls: &mut FitnessControls, population: &mut Population) {
+    let mut fitness = controls.target.0;
+    ui.horizontal(|ui| {
+        ui.label("Target color");
+        ui.color_edit_button_rgb(&mut fitness.target);
+    });
+    egui::ComboBox::from_label("Fitness")
+        .selected_text(fitness.metric.name())
+        .show_ui(ui, |ui| {
+            for metric in ColorMetric::ALL {
+                ui.selectable_value(&mut fitness.metric, metric, metric.name());
+            }
+        });
+
+    if fitness != controls.target.0 {
+        controls.target.0 = fitness;
+        population.set_fitness(fitness);
+        controls.hall_of_fame.rescore(&fitness);
+        controls.termination.reset_best();
+    }
+}
+
+// Pick the selection strategy and tune its parameter
+fn selection_ui(ui: &mut egui::Ui, selection: &mut SelectionStrategy) {
+    egui::ComboBox::from_label("Selection")
+        .selected_text(selection.name())
+        .show_ui(ui, |ui| {
+            for strategy in SelectionStrategy::ALL {
+                if ui.selectable_label(selection.same_kind(&strategy), strategy.name()).clicked() {
+                    *selection = strategy;
+                }
+            }
+        });
+
+    match selection {
+        SelectionStrategy::Tournament { k } => {
+            ui.add(egui::Slider::new(k, 2..=10).text("Tournament size"));
+        }
+        SelectionStrategy::Truncation { fraction } => {
+            ui.add(egui::Slider::new(fraction, 0.05..=1.0).text("Kept fraction"));
+        }
+        SelectionStrategy::Boltzmann { temperature } => {
+            ui.add(egui::Slider::new(temperature, 0.05..=10.0).logarithmic(true).text("Temperature"));
+        }
+        _ => {}
+    }
+}
+
+// Top of the population size slider, a config can go higher
+const MAX_SLIDER_POPULATION: usize = 200_000;
+
+// Population size and replacement model, applied from the next generation
+fn replacement_ui(ui: &mut egui::Ui, population: &mut Population) {
+    // Wide enough for instanced runs, the slider clamps whatever it shows
+    let max_size = population.size.max(MAX_SLIDER_POPULATION);
+    ui.add(egui::Slider::new(&mut population.size, 2..=max_size).logarithmic(true).text("Population size"));
+    // At least one child per generation
+    let max_elites = population.size.saturating_sub(1).min(20);
+    population.elite_count = population.elite_count.min(max_elites);
+    ui.add(egui::Slider::new(&mut population.elite_count, 0..=max_elites).text("Elites"));
+
+    let size = population.size;
+    let elites = population.elite_count;
+    let replacement = &mut population.replacement;
+    egui::ComboBox::from_label("Replacement")
+        .selected_text(replacement.name())
+        .show_ui(ui, |ui| {
+            for model in ReplacementModel::ALL {
+                if ui.selectable_label(replacement.same_kind(&model), model.name()).clicked() {
+                    *replacement = model;
+                }
+            }
+        });
+
+    match replacement {
+        ReplacementModel::SteadyState { replaced } => {
+            ui.add(egui::Slider::new(replaced, 1..=size).text("Replaced per generation"));
+        }
+        ReplacementModel::MuPlusLambda { lambda } => {
+            ui.add(egui::Slider::new(lambda, 1..=size * 4).text("Lambda"));
+        }
+        // Fewer children than mu would shrink the population
+        ReplacementModel::MuCommaLambda { lambda } => {
+            ui.add(egui::Slider::new(lambda, size.saturating_sub(elites).max(1)..=size * 4).text("Lambda"));
+        }
+        _ => {}
+    }
+}
+
+// Panmictic or one of the cellular neighborhoods
+// Crowding pairs parents at random, so it's greyed out next to a spatial
+// neighborhood and the other way around, like validate() refuses it
+fn neighborhood_ui(ui: &mut egui::Ui, neighborhood: &mut Neighborhood, niching: Niching) {
+    egui::ComboBox::from_label("Neighborhood")
+        .selected_text(neighborhood.name())
+        .show_ui(ui, |ui| {
+            for kind in Neighborhood::ALL {
+                let allowed = !kind.is_spatial() || niching != Niching::Crowding;
+                let label = egui::SelectableLabel::new(neighborhood.same_kind(&kind), kind.name());
+                if ui.add_enabled(allowed, label).on_disabled_hover_text("Not with deterministic crowding").clicked() {
+                    *neighborhood = kind;
+                }
+            }
+        });
+
+    match neighborhood {
+        Neighborhood::Radius { radius } => {
+            ui.add(egui::Slider::new(radius, 0.1..=10.0).text("Mating radius"));
+        }
+        Neighborhood::Lattice { spacing, range } => {
+            ui.add(egui::Slider::new(spacing, 0.2..=3.0).text("Lattice spacing"));
+            ui.add(egui::Slider::new(range, 1..=3).text("Lattice range"));
+        }
+        _ => {}
+    }
+}
+
+// Niching method and its parameters, distances count differing genes
+fn niching_ui(ui: &mut egui::Ui, niching: &mut Niching, neighborhood: Neighborhood) {
+    egui::ComboBox::from_label("Niching")
+        .selected_text(niching.name())
+        .show_ui(ui, |ui| {
+            for method in Niching::ALL {
+                let allowed = method != Niching::Crowding || !neighborhood.is_spatial();
+                let label = egui::SelectableLabel::new(niching.same_kind(&method), method.name());
+                if ui.add_enabled(allowed, label).on_disabled_hover_text("Not with a spatial neighborhood").clicked() {
+                    *niching = method;
+                }
+            }
+        });
+
+    match niching {
+        Niching::Sharing { radius, alpha } => {
+            ui.add(egui::Slider::new(radius, 0.5..=6.0).text("Sharing radius"));
+            ui.add(egui::Slider::new(alpha, 0.1..=4.0).text("Alpha"));
+        }
+        Niching::Clearing { radius, capacity } => {
+            ui.add(egui::Slider::new(radius, 0.5..=6.0).text("Clearing radius"));
+            ui.add(egui::Slider::new(capacity, 1..=20).text("Winners per niche"));
+        }
+        Niching::Speciation { threshold } => {
+            ui.add(egui::Slider::new(threshold, 0.5..=6.0).text("Compatibility threshold"));
+        }
+        _ => {}
+    }
+}
+
+// Operators can be switched while the simulation runs
+fn crossover_ui(ui: &mut egui::Ui, population: &mut Population) {
+    let crossover = &mut population.crossover;
+    egui::ComboBox::from_label("Crossover")
+        .selected_text(crossover.name())
+        .show_ui(ui, |ui| {
+            for operator in CrossoverOperator::ALL {
+                if ui.selectable_label(crossover.same_kind(&operator), operator.name()).clicked() {
+                    *crossover = operator;
+                }
+            }
+        });
+
+    match crossover {
+        CrossoverOperator::BlendAlpha { alpha } => {
+            ui.add(egui::Slider::new(alpha, 0.0..=1.0).text("Alpha"));
+        }
+        CrossoverOperator::SimulatedBinary { eta } => {
+            ui.add(egui::Slider::new(eta, 0.0..=20.0).text("Eta"));
+        }
+        _ => {}
+    }
+
+    ui.add(egui::Slider::new(&mut population.crossover_rate, 0.0..=1.0).text("Crossover rate"));
+    ui.checkbox(&mut population.two_offspring, "Two offspring per pair");
+}
+
+// Every mutation has its own operator and rate, they run top to bottom
+fn mutation_ui(ui: &mut egui::Ui, mutations: &mut Vec<Mutation>) {
+    let mut removed = None;
+    for (i, mutation) in mutations.iter_mut().enumerate() {
+        ui.push_id(i, |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("operator")
+                    .selected_text(mutation.operator.name())
+                    .show_ui(ui, |ui| {
+                        for operator in MutationOperator::ALL {
+                            if ui.selectable_label(mutation.operator.same_kind(&operator), operator.name()).clicked() {
+                                mutation.operator = operator;
+                            }
+                        }
+                    });
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+
+            ui.add(egui::Slider::new(&mut mutation.rate, 0.0..=1.0).logarithmic(true).text("Rate"));
+            match &mut mutation.operator {
+                MutationOperator::Creep { step } => {
+                    ui.add(egui::Slider::new(step, 0.0..=2.0).text("Step"));
+                }
+                MutationOperator::Gaussian { sigma } => {
+                    ui.add(egui::Slider::new(sigma, 0.0..=2.0).text("Sigma"));
+                }
+                MutationOperator::Polynomial { eta } => {
+                    ui.add(egui::Slider::new(eta, 0.0..=100.0).text("Eta"));
+                }
+                MutationOperator::YellowBias { growth } => {
+                    ui.add(egui::Slider::new(growth, 0.0..=0.01).text("Growth per generation"));
+                }
+                _ => {}
+            }
+        });
+    }
+
+    if let Some(i) = removed {
+        mutations.remove(i);
+    }
+    if ui.button("Add mutation").clicked() {
+        mutations.push(Mutation::new(MutationOperator::Reset, 0.01));
     }
 }
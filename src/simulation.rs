@@ -1,11 +1,20 @@
 use bevy::prelude::Color;
 use bevy::prelude::*;
-use std::collections::HashMap;
-use bevy::time::Timer;
+use bevy::ecs::system::SystemParam;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
 use rand::Rng;
 
-const POPULATION_SIZE:usize = 350;
-const MUTATION_RATE :f32 = 0.001;
+use algogen::ga::{
+    self, Archipelago, ColorFitness, CubeGenome, GaRng, Population, StatsHistory, StopReason, TerminationCheck,
+};
+
+use crate::checkpoint::{Checkpoint, CubeState};
+use crate::config::SimulationConfig;
+use crate::encounter::{insert_encounters, Encounters};
+use crate::instancing::{instanced, CubeSwarm};
+use crate::world::{island_region, place_in_region};
 
 // All components
 #[derive(Component)]
@@ -17,465 +26,652 @@ pub struct InitPlugin;
 impl Plugin for InitPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(GenerationNumber { current_gen: 1 })
-            .add_systems(Startup, spawn_first_gen)
-            .add_systems(Update, (move_cubes,despawn_cubes));
+            .init_state::<SimulationState>()
+            .add_systems(Startup, (setup_cube_assets, spawn_first_gen))
+            .init_resource::<CheckpointStatus>()
+            .add_event::<CheckpointRequest>()
+            .add_event::<StepGeneration>()
+            .add_systems(Update, checkpoint_system)
+            // The entity cubes, the Instanced render mode has its own systems
+            .add_systems(Update, (move_cubes, restore_cubes).run_if(not(instanced)))
+            // Generations tick at a fixed rate, not once per frame
+            .add_systems(
+                FixedUpdate,
+                (process_generation_system, check_termination)
+                    .chain()
+                    .run_if(in_state(SimulationState::Running).and_then(not(resource_exists::<RunSummary>))),
+            )
+            .add_systems(Update, tick_run_clock.run_if(in_state(SimulationState::Running)))
+            // A manual step can reach a termination criterion too
+            .add_systems(
+                Update,
+                (process_generation_system, check_termination).chain().run_if(on_event::<StepGeneration>()),
+            )
+            .add_systems(
+                Update,
+                apply_generation_rate.run_if(resource_exists_and_changed::<GenerationRate>),
+            )
+            .add_systems(
+                PostUpdate,
+                (sync_population, sync_materials).chain().run_if(not(instanced)),
+            );
     }
 }
 
-#[derive(Component,Debug)]
-struct ParentCube{
-    position:Vec3,
-    color_group: u8
-}
-#[derive(Component,Debug)]
-struct ChildCube;
-#[derive(Component)]
-struct Lifetime {
-    timer: Timer,
+// Setup until the first generation is run, Finished once a termination
+// criterion stopped the run
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SimulationState {
+    #[default]
+    Setup,
+    Running,
+    Paused,
+    Finished,
 }
+
+// One cube mesh and one material per color group, shared by every cube so
+// assets don't grow with the generations
 #[derive(Resource)]
-pub struct GenerationNumber{
-    pub current_gen: u32,
+pub struct CubeAssets {
+    pub mesh: Handle<Mesh>,
+    pub materials: Vec<Handle<StandardMaterial>>,
 }
-// A custom component for the color group
-#[derive(Component,Debug, Clone, Copy)]
-pub struct ColorGroup(pub u8);
-
 
-impl Default for GenerationNumber{
-    fn default() -> Self{
-        GenerationNumber{ current_gen: 1}
-    } 
+impl CubeAssets {
+    // Groups past the palette share its last color, like group_color does
+    pub fn material(&self, color_group: u8) -> Handle<StandardMaterial> {
+        self.materials[(color_group as usize).min(self.materials.len() - 1)].clone()
+    }
 }
 
+// Runs a single generation, sent by the step button
+#[derive(Event)]
+pub struct StepGeneration;
 
-pub fn process_generation_system(
-    mut commands: Commands,
-    query: Query<(Entity, &Handle<StandardMaterial>, Option<&Parent>, Option<&Children>, Option<&Transform>, Option<&ColorGroup>)>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut generate_counter: ResMut<GenerationNumber>,
-) {
-    process_generation(&mut commands, query, &mut materials, &mut meshes, &mut generate_counter, 0.1);
-}
+// Generations per second while running
+#[derive(Resource)]
+pub struct GenerationRate(pub f64);
 
-fn process_generation(
-    commands: &mut Commands,
-    mut query: Query<(Entity, &Handle<StandardMaterial>, Option<&Parent>, Option<&Children>, Option<&Transform>, Option<&ColorGroup>)>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    generate_counter: &mut ResMut<GenerationNumber>,
-    base_mutation_rate: f32, 
-) {
-    // 1. Determine parent entities and relationshipsa
-    let (parent_entities, parent_to_children) = determine_parents(&query);
-    // 2. Perform crossover and mutation to create child genes
-    let new_cubes = perform_crossover(&parent_entities, &query, MUTATION_RATE, generate_counter);
-    // 3. Spawn the new generation of cubes
-    spawn_child_cubes(commands, materials, meshes, new_cubes);
+// The headless GA population, the cubes only mirror it
+#[derive(Resource, Deref, DerefMut)]
+pub struct CorePopulation(pub Population);
 
-    // 4. Increment generation counter
-    generate_counter.current_gen += 1;
+// The sub-populations of an island run. CorePopulation holds their union and
+// its settings are shared by every island.
+#[derive(Resource, Deref, DerefMut)]
+pub struct Islands(pub Archipelago);
 
-    // re-evaluate_fitness
-    evaluate_fitness(commands, query, materials);
+// The only RNG the GA uses, so runs with the same seed and config are
+// identical. Visual-only randomness (cube velocities) stays out of it.
+#[derive(Resource)]
+pub struct SimRng {
+    pub seed: u64,
+    pub rng: GaRng,
+}
 
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng {
+            seed,
+            rng: ga::seeded_rng(seed),
+        }
+    }
 }
 
+// Stats of every generation so far, exportable from the control window
+#[derive(Resource, Deref, DerefMut)]
+pub struct GenerationHistory(pub StatsHistory);
 
-fn spawn_first_gen(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let mut rng = rand::thread_rng();
-    for _ in 0..POPULATION_SIZE {
-        // Spawn the parent cube
-        let random_x = rng.gen_range(-3.0..7.0);
-        let random_y = rng.gen_range(-5.0..7.0);
-        let random_z = rng.gen_range(-3.0..7.0);
-
-        let velocity = Vec3::new(
-            rng.gen_range(-0.1..0.1),
-            rng.gen_range(-0.1..0.1),
-            rng.gen_range(-0.1..0.1),
-        );
-
-        let parent_position = Vec3::new(random_x, random_y, random_z);
-
-        // Generate the material for the parent (cube 1)
-        let parent_color_group = rng.gen_range(0..6); // Random color group for the parent cube
-        let parent_material = generate_material(parent_color_group, &mut materials);
-
-        // Spawn the parent cube with Lifetime
-        let parent_entity = commands
-            .spawn(PbrBundle {
-                mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
-                material: parent_material,
-                transform: Transform {
-                    translation: parent_position,
-                    scale: Vec3::splat(0.3),
-                    ..default()
-                },
-                ..default()
-            })
-            .insert(Mover { velocity })
-            .insert(ParentCube {
-                position: parent_position,
-                color_group: parent_color_group,
-            })
-            .insert(ColorGroup(parent_color_group))
-            .id(); // Save the entity ID to use as a parent
-
-        // Spawn the specified number of child cubes
-        for i in 0..5 {
-            // Generate a random color group for each child
-            let child_color_group = rng.gen_range(0..5);
-            let child_material = generate_material(child_color_group, &mut materials);
-
-            let offset = Vec3::new(i as f32 * 1.0, 0.0, 0.0); // Apply a 1.5 unit offset to each child
-
-            let child_entity = commands
-                .spawn(PbrBundle {
-                    mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
-                    material: child_material,
-                    transform: Transform {
-                        translation: Vec3::new(1.0, 0.0, 0.0) + offset, // Apply offset to parent position
-                        scale: Vec3::splat(0.5),
-                        ..default()
-                    },
-                    ..default()
-                })
-                .insert(ChildCube)
-                .insert(ColorGroup(child_color_group))
-                .id();
+// The best individuals of the whole run
+#[derive(Resource, Deref, DerefMut)]
+pub struct HallOfFame(pub ga::HallOfFame);
 
-            // Parent the child to the parent cube
-            commands.entity(parent_entity).add_child(child_entity);
+// Stop conditions of the run and how long it has been running
+#[derive(Resource)]
+pub struct Termination {
+    check: TerminationCheck,
+    elapsed: Duration,
+}
+
+impl Termination {
+    fn new(config: &SimulationConfig) -> Self {
+        Termination {
+            check: TerminationCheck::new(config.termination),
+            elapsed: Duration::ZERO,
         }
     }
+
+    // The best fitness so far, forgotten when the target changes
+    pub fn reset_best(&mut self) {
+        self.check.reset();
+    }
+}
+
+// Why and when a run stopped, present in the Finished state
+#[derive(Resource, Debug, Clone)]
+pub struct RunSummary {
+    pub reason: StopReason,
+    pub generation: u32,
+    // Best fitness since the start or the last target change
+    pub best: f32,
+    pub elapsed: Duration,
+}
+
+// Target color and metric picked in the control window
+#[derive(Resource)]
+pub struct TargetFitness(pub ColorFitness);
+
+// Sent by the control window
+#[derive(Event)]
+pub enum CheckpointRequest {
+    Save(PathBuf),
+    Load(PathBuf),
+}
+
+// Outcome of the last checkpoint request, shown in the control window
+#[derive(Resource, Default)]
+pub struct CheckpointStatus(pub String);
+
+// Checkpoint given on the command line, used instead of a random first generation
+#[derive(Resource)]
+pub struct ResumeCheckpoint(pub Checkpoint);
+
+// Saved cube positions and velocities, applied once the cubes are spawned
+#[derive(Resource)]
+pub(crate) struct SavedCubes(pub HashMap<u64, CubeState>);
+
+// Everything a checkpoint is made of
+#[derive(SystemParam)]
+struct RunState<'w> {
+    population: Res<'w, CorePopulation>,
+    rng: Res<'w, SimRng>,
+    history: Res<'w, GenerationHistory>,
+    hall_of_fame: Res<'w, HallOfFame>,
+    target: Res<'w, TargetFitness>,
+    termination: Res<'w, Termination>,
+    rate: Res<'w, GenerationRate>,
+    config: Res<'w, SimulationConfig>,
+    islands: Option<Res<'w, Islands>>,
+    encounters: Option<Res<'w, Encounters>>,
+}
+
+#[derive(Component,Debug)]
+pub(crate) struct ParentCube{
+    pub id: u64,
+}
+// Island a parent cube lives on, 0 without islands
+#[derive(Component, Debug)]
+struct OnIsland(usize);
+// One gene cube of a parent, colored after genes[locus] of its Genome
+#[derive(Component,Debug)]
+struct Gene {
+    locus: usize,
+}
+// The chromosome of a parent cube, the only place the scene reads genes
+// from. Materials are derived from it by sync_materials.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Genome(pub CubeGenome);
+#[derive(Resource)]
+pub struct GenerationNumber{
+    pub current_gen: u32,
 }
 
-// generate color for the cubes.
-fn generate_material(
-    color_group: u8,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-) -> Handle<StandardMaterial> {
-    // Generate a color based on the color group
-    let color = match color_group {
-        0 => Color::srgb(1.0, 0.0, 0.0), // Red
-        1 => Color::srgb(0.0, 1.0, 0.0), // Green
-        2 => Color::srgb(0.0, 0.0, 1.0), // Blue
-        3 => Color::srgb(1.0, 1.0, 0.0), // Yellow
-        _ => Color::srgb(1.0, 1.0, 1.0), // Default White
-    };
 
-    // Create and return a new material
-    materials.add(StandardMaterial {
-        base_color: color,
-        ..default()
-    })
+impl Default for GenerationNumber{
+    fn default() -> Self{
+        GenerationNumber{ current_gen: 1}
+    }
 }
 
-fn move_cubes(mut query: Query<(&mut Mover, &mut Transform)>, time: Res<Time>) {
-    
-    let speed_multiplier = 3.0;
 
-    for (mut mover, mut transform) in query.iter_mut() {
-        let scaled_velocity = mover.velocity * speed_multiplier;
-        // Update position based on velocity
-        transform.translation += scaled_velocity * time.delta_seconds();
+#[allow(clippy::too_many_arguments)]
+fn process_generation_system(
+    mut population: ResMut<CorePopulation>,
+    mut generate_counter: ResMut<GenerationNumber>,
+    mut rng: ResMut<SimRng>,
+    mut history: ResMut<GenerationHistory>,
+    mut hall_of_fame: ResMut<HallOfFame>,
+    config: Res<SimulationConfig>,
+    islands: Option<ResMut<Islands>>,
+    encounters: Option<Res<Encounters>>,
+) {
+    process_generation(
+        &mut population,
+        &mut rng,
+        &mut history,
+        &mut hall_of_fame,
+        &config,
+        islands.map(|islands| islands.into_inner()),
+        encounters.is_some(),
+    );
+    generate_counter.current_gen = population.generation;
+}
 
-        // Check for boundary collisions and reverse velocity if necessary
-        if transform.translation.x <= -9.0 || transform.translation.x >= 9.0 {
-            mover.velocity.x = -mover.velocity.x; // Reverse X velocity
-            transform.translation.x = transform.translation.x.clamp(-9.0, 9.0); // Ensure within bounds
+fn process_generation(
+    population: &mut ResMut<CorePopulation>,
+    rng: &mut SimRng,
+    history: &mut GenerationHistory,
+    hall_of_fame: &mut HallOfFame,
+    config: &SimulationConfig,
+    islands: Option<&mut Islands>,
+    encounters: bool,
+) {
+    // Nothing left to breed, the run is about to finish
+    if population.individuals.is_empty() {
+        return;
+    }
+    // Crossover, mutation and fitness evaluation all happen in the core
+    match islands {
+        Some(islands) => {
+            islands.evolve(population, &mut rng.rng);
+            islands.cull(population, config.cull_fitness);
         }
-        if transform.translation.y <= -9.0 || transform.translation.y >= 9.0 {
-            mover.velocity.y = -mover.velocity.y; // Reverse Y velocity
-            transform.translation.y = transform.translation.y.clamp(-9.0, 9.0); // Ensure within bounds
+        // The cubes bred on their own since the last generation, see encounter.rs
+        None if encounters => {
+            population.census();
+            population.cull(config.cull_fitness);
         }
-        if transform.translation.z <= -9.0 || transform.translation.z >= 9.0 {
-            mover.velocity.z = -mover.velocity.z; // Reverse Z velocity
-            transform.translation.z = transform.translation.z.clamp(-9.0, 9.0); // Ensure within bounds
+        None => {
+            population.step(&mut rng.rng);
+            population.cull(config.cull_fitness);
         }
     }
+    history.push(population.stats());
+    hall_of_fame.update(population);
 }
 
-//Helper function to compare colors with a tolerance
-fn colors_are_equal(color1: Color, color2: Color, tolerance: f32) -> bool {
-    let [r1, g1, b1, _] = color1.to_linear().to_u8_array();
-    let [r2, g2, b2, _] = color2.to_linear().to_u8_array();
 
-    // Convert to f32 values for comparison
-    let r1 = r1 as f32 / 255.0;
-    let g1 = g1 as f32 / 255.0;
-    let b1 = b1 as f32 / 255.0;
-
-    let r2 = r2 as f32 / 255.0;
-    let g2 = g2 as f32 / 255.0;
-    let b2 = b2 as f32 / 255.0;
+fn spawn_first_gen(mut commands: Commands, config: Res<SimulationConfig>, resume: Option<Res<ResumeCheckpoint>>) {
+    if let Some(resume) = resume {
+        apply_checkpoint(&mut commands, resume.0.clone());
+        commands.remove_resource::<ResumeCheckpoint>();
+        return;
+    }
 
-    // Compare each component's difference against the tolerance
-    (r1 - r2).abs() < tolerance
-        && (g1 - g2).abs() < tolerance
-        && (b1 - b2).abs() < tolerance
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let mut rng = SimRng::new(seed);
+    let mut population = config.population(&mut rng.rng);
+    let islands = config.archipelago(&mut population, &mut rng.rng);
+    insert_islands(&mut commands, islands);
+    insert_encounters(&mut commands, &config);
+    let mut history = StatsHistory::new(seed);
+    history.push(population.stats());
+    let mut hall_of_fame = ga::HallOfFame::new(config.hall_of_fame_size);
+    hall_of_fame.update(&population);
+    commands.insert_resource(GenerationNumber { current_gen: population.generation });
+    commands.insert_resource(HallOfFame(hall_of_fame));
+    commands.insert_resource(GenerationHistory(history));
+    commands.insert_resource(rng);
+    commands.insert_resource(CorePopulation(population));
+    commands.insert_resource(TargetFitness(config.fitness));
+    commands.insert_resource(Termination::new(&config));
+    commands.insert_resource(GenerationRate(config.generations_per_second));
 }
 
-pub fn evaluate_fitness(
-    commands: &mut Commands,
-    mut query: Query<(
-        Entity,
-        &Handle<StandardMaterial>,
-        Option<&Parent>,
-        Option<&Children>,
-        Option<&Transform>,
-        Option<&ColorGroup>,
-    )>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-) {
-    let yellow = Color::srgb(1.0, 1.0, 0.0); // Yellow color definition
-    let tolerance = 0.01; // Tolerance for color comparison
-
-    for (entity, material_handle, parent, children, _, color_group) in query.iter() {
-        // Get the color of the entity
-        let is_entity_yellow = if let Some(material) = materials.get(material_handle) {
-            colors_are_equal(material.base_color, yellow, tolerance)
-        } else {
-            false
-        };
+// Replaces the whole run with the saved one
+fn apply_checkpoint(commands: &mut Commands, checkpoint: Checkpoint) {
+    let population = checkpoint.population();
+    insert_islands(commands, checkpoint.archipelago(&population));
+    let cubes = checkpoint.cubes.iter().map(|cube| (cube.id, *cube)).collect();
+    commands.insert_resource(GenerationNumber { current_gen: population.generation });
+    commands.insert_resource(GenerationHistory(checkpoint.history));
+    commands.insert_resource(HallOfFame(checkpoint.hall_of_fame));
+    commands.insert_resource(SimRng {
+        seed: checkpoint.seed,
+        rng: checkpoint.rng,
+    });
+    commands.insert_resource(CorePopulation(population));
+    commands.insert_resource(TargetFitness(checkpoint.config.fitness));
+    let mut termination = Termination::new(&checkpoint.config);
+    termination.check.restore(checkpoint.termination);
+    commands.insert_resource(termination);
+    commands.insert_resource(GenerationRate(checkpoint.config.generations_per_second));
+    commands.remove_resource::<RunSummary>();
+    insert_encounters(commands, &checkpoint.config);
+    commands.insert_resource(checkpoint.config);
+    commands.insert_resource(SavedCubes(cubes));
+}
 
-        // Calculate fitness score for the cube based on its distance to yellow
-        let fitness_score = if let Some(material) = materials.get(material_handle) {
-            calculate_fitness_score(material.base_color, yellow)
-        } else {
-            0.0
-        };
+fn insert_islands(commands: &mut Commands, islands: Option<Archipelago>) {
+    match islands {
+        Some(islands) => commands.insert_resource(Islands(islands)),
+        None => commands.remove_resource::<Islands>(),
+    }
+}
 
-        // Handle parent-child logic
-        if let Some(parent) = parent {
-            // This is a child entity
-            if let Ok((_, parent_material_handle, _, _, _, _)) = query.get(parent.get()) {
-                let parent_fitness_score = if let Some(parent_material) = materials.get(parent_material_handle) {
-                    calculate_fitness_score(parent_material.base_color, yellow)
-                } else {
-                    0.0
-                };
-
-                // If neither parent nor child is yellow and has low fitness, despawn child
-                if !is_entity_yellow && fitness_score < 0.9 {
-                    commands.entity(entity).despawn_recursive(); // Eliminate weaker non-yellow cubes
+fn checkpoint_system(
+    mut commands: Commands,
+    mut requests: EventReader<CheckpointRequest>,
+    run: RunState,
+    cubes: Query<(Entity, &ParentCube, &Transform, &Mover)>,
+    swarm: Option<Res<CubeSwarm>>,
+    mut status: ResMut<CheckpointStatus>,
+    mut next_state: ResMut<NextState<SimulationState>>,
+) {
+    for request in requests.read() {
+        status.0 = match request {
+            CheckpointRequest::Save(path) => {
+                let mut cubes: Vec<CubeState> = cubes
+                    .iter()
+                    .map(|(_, parent_cube, transform, mover)| CubeState {
+                        id: parent_cube.id,
+                        position: transform.translation.to_array(),
+                        velocity: mover.velocity.to_array(),
+                    })
+                    .collect();
+                // Only one of the two render modes has cubes
+                if let Some(swarm) = &swarm {
+                    cubes.extend((0..swarm.ids.len()).map(|slot| CubeState {
+                        id: swarm.ids[slot],
+                        position: swarm.positions[slot].to_array(),
+                        velocity: swarm.velocities[slot].to_array(),
+                    }));
+                }
+                let mut config = run.config.clone();
+                config.fitness = run.target.0;
+                config.generations_per_second = run.rate.0;
+                if let Some(encounters) = &run.encounters {
+                    config.encounters = encounters.settings;
+                }
+                let checkpoint = Checkpoint::new(
+                    &config,
+                    &run.population,
+                    run.islands.as_deref().map(|islands| &islands.0),
+                    run.rng.seed,
+                    &run.rng.rng,
+                    &run.history,
+                    &run.hall_of_fame,
+                    run.termination.check.state(),
+                    cubes,
+                );
+                match checkpoint.save(path) {
+                    Ok(()) => format!("Saved generation {} to {}", run.population.generation, path.display()),
+                    Err(err) => format!("Save failed: {err}"),
                 }
             }
-
-        } else if let Some(children) = children {
-            // This is a parent entity
-            let mut has_yellow_child = false;
-
-            for &child in children.iter() {
-                if let Ok((_, child_material_handle, _, _, _, _)) = query.get(child) {
-                    if let Some(child_material) = materials.get(child_material_handle) {
-                        if colors_are_equal(child_material.base_color, yellow, tolerance) {
-                            has_yellow_child = true;
-                            break;
-                        }
+            CheckpointRequest::Load(path) => match Checkpoint::load(path) {
+                Ok(mut checkpoint) => {
+                    // The scene can't switch render mode or thread count while running
+                    checkpoint.config.render_mode = run.config.render_mode;
+                    checkpoint.config.threads = run.config.threads;
+                    // The ids of the loaded run can clash with the current cubes
+                    for (entity, ..) in cubes.iter() {
+                        commands.entity(entity).despawn_recursive();
                     }
+                    let generation = checkpoint.population.generation;
+                    apply_checkpoint(&mut commands, checkpoint);
+                    next_state.set(SimulationState::Paused);
+                    format!("Loaded generation {} from {}", generation, path.display())
                 }
-            }
-
-            // Despawn parent and children if no yellow exists or fitness is low
-            if !is_entity_yellow && !has_yellow_child && fitness_score < 0.2 {
-                commands.entity(entity).despawn_recursive();
-            }
-        }
+                Err(err) => format!("Load failed: {err}"),
+            },
+        };
     }
 }
 
-// Helper function to calculate fitness score based on color distance
-fn calculate_fitness_score(color: Color, target: Color) -> f32 {
-    let [r1, g1, b1, _] = color.to_linear().to_u8_array(); // Decompose color1 into components
-    let [r2, g2, b2, _] = target.to_linear().to_u8_array(); // Decompose color2 into components
-
-    // Convert to f32 values for comparison
-    let r1 = r1 as f32 / 255.0;
-    let g1 = g1 as f32 / 255.0;
-    let b1 = b1 as f32 / 255.0;
-
-    let r2 = r2 as f32 / 255.0;
-    let g2 = g2 as f32 / 255.0;
-    let b2 = b2 as f32 / 255.0;
-
-    // Calculate Euclidean distance between colors
-    let distance = ((r1 - r2).powi(2) + (g1 - g2).powi(2) + (b1 - b2).powi(2)).sqrt();
-
-    // The closer to yellow (0 distance), the higher the fitness score
-    if distance < 0.1 {
-        2.0 // Perfect match to yellow
-    } else {
-        (1.0 / (1.0 + distance)).powi(3)
+// Wall-clock time spent running, pauses excluded
+fn tick_run_clock(termination: Option<ResMut<Termination>>, time: Res<Time>) {
+    if let Some(mut termination) = termination {
+        termination.elapsed += time.delta();
     }
 }
 
-fn determine_parents(
-    query: &Query<(Entity, &Handle<StandardMaterial>, Option<&Parent>, Option<&Children>, Option<&Transform>, Option<&ColorGroup>)>,
-) -> (Vec<Entity>, HashMap<Entity, Vec<Entity>>) {
-    let mut parent_to_children: HashMap<Entity, Vec<Entity>> = HashMap::new();
-    let mut parent_entities: Vec<Entity> = Vec::new();
+// Finishes the run once a termination criterion is met, checked after every
+// generation so none runs past it
+fn check_termination(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<SimulationState>>,
+    population: Res<CorePopulation>,
+    mut termination: ResMut<Termination>,
+) {
+    let elapsed = termination.elapsed;
+    if let Some(reason) = termination.check.check(&population, elapsed) {
+        next_state.set(SimulationState::Finished);
+        commands.insert_resource(RunSummary {
+            reason,
+            generation: population.generation,
+            best: termination.check.state().best.unwrap_or(f32::NEG_INFINITY),
+            elapsed,
+        });
+    }
+}
 
-    for (entity, _, parent, children, _, _) in query.iter() {
-        if let Some(parent) = parent {
-            parent_to_children.entry(parent.get()).or_insert_with(Vec::new).push(entity);
-        } else {
-            parent_entities.push(entity);
-        }
+fn apply_generation_rate(rate: Res<GenerationRate>, mut time: ResMut<Time<Fixed>>) {
+    time.set_timestep_hz(rate.0);
+}
 
-        if let Some(children) = children {
-            for &child in children.iter() {
-                parent_to_children.entry(entity).or_insert_with(Vec::new).push(child);
-            }
+// Puts the cubes of a loaded checkpoint back where they were
+fn restore_cubes(
+    mut commands: Commands,
+    saved: Option<ResMut<SavedCubes>>,
+    mut cubes: Query<(&ParentCube, &mut Transform, &mut Mover), Added<ParentCube>>,
+) {
+    let Some(mut saved) = saved else {
+        return;
+    };
+    for (parent_cube, mut transform, mut mover) in cubes.iter_mut() {
+        if let Some(cube) = saved.0.remove(&parent_cube.id) {
+            transform.translation = Vec3::from_array(cube.position);
+            mover.velocity = Vec3::from_array(cube.velocity);
         }
     }
-
-    (parent_entities, parent_to_children)
+    if saved.0.is_empty() {
+        commands.remove_resource::<SavedCubes>();
+    }
 }
 
+// Spawns and despawns cubes so the scene matches the core population
+fn sync_population(
+    mut commands: Commands,
+    population: Option<Res<CorePopulation>>,
+    mut parents: Query<(Entity, &ParentCube, &mut Genome, &mut OnIsland)>,
+    assets: Res<CubeAssets>,
+    config: Res<SimulationConfig>,
+    islands: Option<Res<Islands>>,
+) {
+    let Some(population) = population else {
+        return;
+    };
+    if !population.is_changed() {
+        return;
+    }
+    let island_of = islands.map(|islands| islands.island_of()).unwrap_or_default();
+    let regions = island_regions(&config);
 
-fn perform_crossover(
-    parent_entities: &[Entity],
-    query: &Query<(Entity, &Handle<StandardMaterial>, Option<&Parent>, Option<&Children>, Option<&Transform>, Option<&ColorGroup>)>,
-    mutation_rate: f32,
-    generate_counter: &mut ResMut<GenerationNumber>,
-) -> Vec<(u8, Vec3)> {
-
-    let mut rng = rand::thread_rng();
-    let mut new_cubes: Vec<(u8, Vec3)> = Vec::new();
-
-    for i in (0..parent_entities.len()).step_by(2) {
-        if i + 1 < parent_entities.len() {
-            let parent1 = parent_entities[i];
-            let parent2 = parent_entities[i + 1];
-
-            if let (
-                Ok((_, _, _, _, Some(transform1), Some(color_group1))),
-                Ok((_, _, _, _, Some(transform2), Some(color_group2))),
-            ) = (query.get(parent1), query.get(parent2))
-            {
-                // Gene slicing using arithmetic crossover
-                let slice_point = rng.gen_range(0.0..=1.0);
-                // Sliced color group
-                let child_color_group = if color_group1.0 == 3 || color_group2.0 == 3 {
-                    3 // Prioritize yellow parents
-                } else {
-                    if slice_point < 0.5 {
-                        color_group1.0
-                    } else {
-                        color_group2.0
-                    }
-                };
-
-                // Sliced position (weighted average based on slice point)
-                let child_position = Vec3::new(
-                    transform1.translation.x * slice_point
-                        + transform2.translation.x * (1.0 - slice_point),
-                    transform1.translation.y * slice_point
-                        + transform2.translation.y * (1.0 - slice_point),
-                    transform1.translation.z * slice_point
-                        + transform2.translation.z * (1.0 - slice_point),
-                );
-
-                // Apply mutation
-                let (mutated_position, mutated_color_group) =
-                    mutate(child_position, child_color_group, mutation_rate,generate_counter );
+    let individuals: HashMap<u64, &CubeGenome> =
+        population.individuals.iter().map(|individual| (individual.id, &individual.genome)).collect();
+    let mut spawned: HashSet<u64> = HashSet::new();
 
-                // Add new cube data
-                new_cubes.push((mutated_color_group, mutated_position));
-            }
+    for (entity, parent_cube, mut genome, mut island) in parents.iter_mut() {
+        let Some(&core_genome) = individuals.get(&parent_cube.id) else {
+            // Eliminated by the core
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        // Only touch the component when the genes really changed
+        if genome.0 != *core_genome {
+            genome.0 = core_genome.clone();
         }
+        // Migrants fly over on their own, see move_cube
+        let home = island_of.get(&parent_cube.id).copied().unwrap_or_default();
+        if island.0 != home {
+            island.0 = home;
+        }
+        spawned.insert(parent_cube.id);
     }
 
-    new_cubes
+    let mut rng = rand::thread_rng();
+    for individual in population.individuals.iter() {
+        if !spawned.contains(&individual.id) {
+            let island = island_of.get(&individual.id).copied().unwrap_or_default();
+            let position = place_in_region(
+                Vec3::from_array(individual.genome.position),
+                config.world_bounds,
+                regions[island.min(regions.len() - 1)],
+            );
+            spawn_parent_cube(&mut commands, individual, position, island, config.max_velocity, &mut rng, &assets);
+        }
+    }
 }
 
-fn spawn_child_cubes(
+fn spawn_parent_cube(
     commands: &mut Commands,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    new_cubes: Vec<(u8, Vec3)>,
+    individual: &ga::Individual,
+    parent_position: Vec3,
+    island: usize,
+    max_velocity: f32,
+    rng: &mut impl Rng,
+    assets: &CubeAssets,
 ) {
-    // Precreate the mesh to avoid recreating it every time
-    let cube_mesh = meshes.add(Cuboid::new(1.0, 1.0, 1.0));
-    // Spawn the parent entity
+    let velocity = Vec3::new(
+        rng.gen_range(-max_velocity..max_velocity),
+        rng.gen_range(-max_velocity..max_velocity),
+        rng.gen_range(-max_velocity..max_velocity),
+    );
+
+    // The material is set by sync_materials once the Genome is in
     let parent_entity = commands
-        .spawn((
-            Transform::default(),
-            GlobalTransform::default(),
-            Name::new("ParentEntity"),
-        ))
-        .id();
-
-    // Iterate over each new cube spawn request
-    for (color_group, position) in new_cubes {
-        // Generate or reuse the material based on the color group
-        let child_material = generate_material(color_group, materials);
-
-        // Spawn a new cube as a child of the parent entity
-        commands.entity(parent_entity).with_children(|parent| {
-            parent.spawn((
-                PbrBundle {
-                    mesh: cube_mesh.clone(), // Reuse the mesh
-                    material: child_material,
-                    transform: Transform {
-                        translation: position,
-                        scale: Vec3::splat(0.4), // Set the scale for the cubes
-                        ..default()
-                    },
+        .spawn(PbrBundle {
+            mesh: assets.mesh.clone(),
+            transform: Transform {
+                translation: parent_position,
+                scale: Vec3::splat(0.3),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Mover { velocity })
+        .insert(ParentCube { id: individual.id })
+        .insert(OnIsland(island))
+        .insert(Genome(individual.genome.clone()))
+        .id(); // Save the entity ID to use as a parent
+
+    spawn_genes(commands, parent_entity, individual.genome.genes.len(), assets);
+}
+
+// Spawn one child cube per chromosome slot
+fn spawn_genes(
+    commands: &mut Commands,
+    parent_entity: Entity,
+    gene_count: usize,
+    assets: &CubeAssets,
+) {
+    for locus in 0..gene_count {
+        let offset = Vec3::new(locus as f32 * 1.0, 0.0, 0.0); // Apply a 1.5 unit offset to each child
+
+        let child_entity = commands
+            .spawn(PbrBundle {
+                mesh: assets.mesh.clone(),
+                transform: Transform {
+                    translation: Vec3::new(1.0, 0.0, 0.0) + offset, // Apply offset to parent position
+                    scale: Vec3::splat(0.5),
                     ..default()
                 },
-                Lifetime {
-                    timer: Timer::from_seconds(3.0, TimerMode::Once),
-                },
-            ));
-        });
+                ..default()
+            })
+            .insert(Gene { locus })
+            .id();
+
+        // Parent the child to the parent cube
+        commands.entity(parent_entity).add_child(child_entity);
     }
 }
 
-fn despawn_cubes(
-    mut commands: Commands,
-    time: Res<Time>,
-    mut query: Query<(Entity, &mut Lifetime)>,
+// Recolors a parent and its gene cubes whenever its Genome changes
+fn sync_materials(
+    assets: Res<CubeAssets>,
+    mut parents: Query<(&Genome, &Children, &mut Handle<StandardMaterial>), Changed<Genome>>,
+    mut genes: Query<(&Gene, &mut Handle<StandardMaterial>), Without<Genome>>,
 ) {
-    for (entity, mut lifetime) in query.iter_mut() {
-        // Tick the timer
-        lifetime.timer.tick(time.delta());
-
-        // Despawn the entity if the timer has finished
-        if lifetime.timer.finished() {
-            commands.entity(entity).despawn_recursive();
+    for (genome, children, mut material) in parents.iter_mut() {
+        *material = assets.material(genome.0.color_group);
+        for &child in children.iter() {
+            if let Ok((gene, mut material)) = genes.get_mut(child) {
+                let color_group = genome.0.genes.get(gene.locus).copied().unwrap_or_default();
+                *material = assets.material(color_group);
+            }
         }
     }
 }
 
-fn mutate(position: Vec3, color_group: u8, mutation_rate: f32, generate_counter: &mut ResMut<GenerationNumber>) -> (Vec3, u8) {
-    let mut rng = rand::thread_rng();
-    let generation = generate_counter.current_gen;
+pub(crate) fn setup_cube_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let materials = (0..ga::COLOR_GROUPS)
+        .map(|color_group| {
+            let [r, g, b] = ga::group_color(color_group);
+            materials.add(StandardMaterial {
+                base_color: Color::srgb(r, g, b),
+                ..default()
+            })
+        })
+        .collect();
+    commands.insert_resource(CubeAssets {
+        mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
+        materials,
+    });
+}
 
-    let yellow_bias = 0.0005 * generation as f32; 
-    let mutation_probability = (mutation_rate + yellow_bias).min(1.0);
+fn move_cubes(
+    mut query: Query<(&mut Mover, &mut Transform, &OnIsland, &Genome)>,
+    time: Res<Time>,
+    config: Res<SimulationConfig>,
+    population: Option<Res<CorePopulation>>,
+) {
+    let regions = island_regions(&config);
+    let spatial = population.is_some_and(|population| population.neighborhood.is_spatial());
+    for (mut mover, mut transform, island, genome) in query.iter_mut() {
+        let region = regions[island.0.min(regions.len() - 1)];
+        if spatial {
+            settle_cube(&mut transform.translation, &genome.0, config.world_bounds, time.delta_seconds(), region);
+        } else {
+            move_cube(&mut transform.translation, &mut mover.velocity, config.speed_multiplier, time.delta_seconds(), region);
+        }
+    }
+}
 
-    // Mutate position: add small random changes within a range
-    let mutated_position = Vec3::new(
-        position.x + rng.gen_range(-mutation_rate..mutation_rate),
-        position.y + rng.gen_range(-mutation_rate..mutation_rate),
-        position.z + rng.gen_range(-mutation_rate..mutation_rate),
-    );
+// How fast a migrant flies to its new island
+const MIGRATION_SPEED: f32 = 6.0;
 
-    // Mutate color group: increase chance of yellow (color_group = 3) over time
-    let mutated_color_group = if rng.gen_bool(mutation_probability as f64) {
-        3  // Prefer yellow as generations increase
-    } else {
-        color_group
-    };
+// Region of every island, the whole world without islands
+pub(crate) fn island_regions(config: &SimulationConfig) -> Vec<(Vec3, Vec3)> {
+    let count = config.islands.count;
+    (0..count.max(1)).map(|island| island_region(config.world_bounds, count, island)).collect()
+}
 
-    (mutated_position, mutated_color_group)
+// With a spatial neighborhood the genome position is where the cube lives
+// and mates, so it flies there and stays put instead of wandering around
+pub(crate) fn settle_cube(position: &mut Vec3, genome: &CubeGenome, bounds: f32, delta: f32, region: (Vec3, Vec3)) {
+    let home = place_in_region(Vec3::from_array(genome.position), bounds, region);
+    let distance = position.distance(home);
+    *position += (home - *position).normalize_or_zero() * distance.min(MIGRATION_SPEED * delta);
 }
 
+// Moves a cube for `delta` seconds inside its region. A cube outside of it,
+// a migrant, first flies straight there.
+pub(crate) fn move_cube(
+    position: &mut Vec3,
+    velocity: &mut Vec3,
+    speed_multiplier: f32,
+    delta: f32,
+    (min, max): (Vec3, Vec3),
+) {
+    if position.cmplt(min).any() || position.cmpgt(max).any() {
+        let center = (min + max) / 2.0;
+        let distance = position.distance(center);
+        *position += (center - *position).normalize_or_zero() * distance.min(MIGRATION_SPEED * delta);
+        return;
+    }
+    // Update position based on velocity
+    *position += *velocity * speed_multiplier * delta;
+
+    // Check for boundary collisions and reverse velocity if necessary
+    for axis in 0..3 {
+        if position[axis] <= min[axis] || position[axis] >= max[axis] {
+            velocity[axis] = -velocity[axis]; // Reverse velocity
+            position[axis] = position[axis].clamp(min[axis], max[axis]); // Ensure within bounds
+        }
+    }
+}
@@ -1,10 +1,94 @@
 use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::config::SimulationConfig;
+use crate::instancing::CubeSwarm;
+use crate::simulation::{CorePopulation, ParentCube};
 
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_light);
+        app.add_systems(Startup, spawn_light)
+            .add_systems(Update, (draw_islands, draw_species));
+    }
+}
+
+// Box the cubes of island `island` out of `count` bounce in. Islands sit side
+// by side on a grid over x and z with a gap between them, a single island
+// gets the whole world.
+pub fn island_region(bounds: f32, count: usize, island: usize) -> (Vec3, Vec3) {
+    if count < 2 {
+        return (Vec3::splat(-bounds), Vec3::splat(bounds));
+    }
+    let columns = (count as f32).sqrt().ceil() as usize;
+    let rows = count.div_ceil(columns);
+    let cell = Vec2::new(2.0 * bounds / columns as f32, 2.0 * bounds / rows as f32);
+    let gap = cell * 0.1;
+    let (column, row) = (island % columns, island / columns);
+    let min = Vec3::new(
+        -bounds + column as f32 * cell.x + gap.x,
+        -bounds,
+        -bounds + row as f32 * cell.y + gap.y,
+    );
+    let max = Vec3::new(min.x + cell.x - 2.0 * gap.x, bounds, min.z + cell.y - 2.0 * gap.y);
+    (min, max)
+}
+
+// Same spot in the region as `position` has in the whole world
+pub fn place_in_region(position: Vec3, bounds: f32, (min, max): (Vec3, Vec3)) -> Vec3 {
+    let share = ((position + bounds) / (2.0 * bounds)).clamp(Vec3::ZERO, Vec3::ONE);
+    min + share * (max - min)
+}
+
+// Colors far apart on the hue wheel for consecutive species ids
+pub fn species_color(id: u32) -> Color {
+    Color::hsl((id as f32 * 137.5) % 360.0, 0.9, 0.6)
+}
+
+// More outlines than this would slow the frame down more than the cubes do
+const MAX_SPECIES_OUTLINES: usize = 5000;
+
+// Outlines every cube in the color of its species, with speciation on
+fn draw_species(
+    mut gizmos: Gizmos,
+    population: Option<Res<CorePopulation>>,
+    cubes: Query<(&ParentCube, &Transform)>,
+    swarm: Res<CubeSwarm>,
+) {
+    let Some(population) = population else {
+        return;
+    };
+    if population.species.is_empty() {
+        return;
+    }
+    let species_of: HashMap<u64, u32> = population
+        .species
+        .iter()
+        .flat_map(|species| species.members.iter().map(move |&id| (id, species.id)))
+        .collect();
+    // Only one of the two render modes has cubes
+    let found = cubes
+        .iter()
+        .map(|(cube, transform)| (cube.id, transform.translation))
+        .chain(swarm.ids.iter().copied().zip(swarm.positions.iter().copied()));
+    for (id, position) in found.take(MAX_SPECIES_OUTLINES) {
+        if let Some(&species) = species_of.get(&id) {
+            gizmos.cuboid(Transform::from_translation(position).with_scale(Vec3::splat(0.4)), species_color(species));
+        }
+    }
+}
+
+// Outlines of the islands
+fn draw_islands(mut gizmos: Gizmos, config: Res<SimulationConfig>) {
+    let count = config.islands.count;
+    if count < 2 {
+        return;
+    }
+    for island in 0..count {
+        let (min, max) = island_region(config.world_bounds, count, island);
+        let transform = Transform::from_translation((min + max) / 2.0).with_scale(max - min);
+        gizmos.cuboid(transform, Color::srgb(0.5, 0.5, 0.5));
     }
 }
 